@@ -1,16 +1,21 @@
 use crate::errors::filesystem::FileSystemError;
-use ainigma::build_process::build_task;
-use ainigma::config::{ModuleConfiguration, read_toml, server_read_check_toml};
+use ainigma::build_process::{BuildOptions, IntermediateOutput, TaskBuildContainer, build_task};
+use ainigma::cache::BuildCache;
+use ainigma::config::{
+    DEFAULT_BUILD_LOG, DEFAULT_BUILD_MANIFEST, ModuleConfiguration, read_toml,
+    server_read_check_toml,
+};
+use ainigma::{markdown, template};
 use axum::Extension;
 use axum::body::Body;
-use axum::extract::Path as AxumPath;
+use axum::extract::{Path as AxumPath, Query};
 use axum::{Json, http::StatusCode, response::IntoResponse, response::Response};
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs::{self, File};
@@ -33,17 +38,34 @@ fn get_data_path() -> PathBuf {
         PathBuf::from("/srv/ainigma/data")
     }
 }
+fn get_cache_path() -> PathBuf {
+    if let Ok(path) = env::var("AINIGMA_CACHE_DIR") {
+        PathBuf::from(path)
+    } else {
+        // Builds are reused when the output directory of a student has been removed
+        get_data_path().join("build-cache")
+    }
+}
 #[derive(Serialize)]
 pub struct FileMetadata {
     pub name: String,
     pub size: u64,
     pub last_modified: String,
+    /// SHA-256 recorded when the variant was built, missing for files the builder did not declare
+    pub sha256: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct TaskMetadataResponse {
     pub instructions: String,
+    /// Instructions rendered from Markdown to sanitized HTML, when requested with `?html=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions_html: Option<String>,
     pub files: Vec<FileMetadata>,
+    /// Address of the variant reported by the builder, such as a deployed challenge instance
+    pub url: Option<String>,
+    /// Other details of the variant reported by the builder
+    pub metadata: BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(Clone, Serialize)]
@@ -139,8 +161,17 @@ pub async fn tasks_handler(
         None => Err((StatusCode::NOT_FOUND, "Tasks not found".to_string())),
     }
 }
+/// Query parameters of the task metadata
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskMetadataQuery {
+    /// Include the instructions rendered as HTML
+    #[serde(default)]
+    pub html: bool,
+}
+
 pub async fn get_task_metadata(
     AxumPath((course_id, category_name, task_id, uuid)): AxumPath<(String, String, String, String)>,
+    Query(query): Query<TaskMetadataQuery>,
 ) -> Result<Json<TaskMetadataResponse>, (StatusCode, String)> {
     if !SAFE_ID_PATTERN.is_match(&course_id)
         || !SAFE_ID_PATTERN.is_match(&category_name)
@@ -179,7 +210,11 @@ pub async fn get_task_metadata(
             Ok(uuid) => uuid,
             Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid UUID".to_string())),
         };
-        let build_result = build_task(&toml, &task_root, &task_id, uuid).await;
+        let options = BuildOptions {
+            cache: Some(BuildCache::new(get_cache_path())),
+            ..Default::default()
+        };
+        let build_result = build_task(&toml, &task_root, &task_id, uuid, &options).await;
         if let Err(err) = build_result {
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -188,8 +223,16 @@ pub async fn get_task_metadata(
         }
     }
 
+    let build = load_build(&output_path);
+    let variant = build.as_ref().and_then(|build| build.outputs.first());
     let student_instructions_path = output_path.join("instructions.md");
-    let instructions = if let Ok(content) = fs::read_to_string(&student_instructions_path).await {
+    let instructions = if let Some(build) = &build
+        && let Some(variant) = variant
+        && build.task.build.readme_template.is_some()
+    {
+        template::instructions(&build.task, &task_root, variant)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    } else if let Ok(content) = fs::read_to_string(&student_instructions_path).await {
         content
     } else {
         //TODO!: Add to checking the instructions exists in config check
@@ -200,6 +243,8 @@ pub async fn get_task_metadata(
         }
     };
 
+    let private = private_files(variant);
+    let checksums = file_checksums(variant);
     let mut files = Vec::new();
     let mut entries = match tokio::fs::read_dir(&output_path).await {
         Ok(e) => e,
@@ -208,7 +253,7 @@ pub async fn get_task_metadata(
 
     while let Ok(Some(entry)) = entries.next_entry().await {
         let file_name = entry.file_name().to_string_lossy().into_owned();
-        if file_name == "instructions.md" || file_name == "build-manifest.json" {
+        if file_name == "instructions.md" || private.contains(&file_name) {
             continue;
         }
         let meta = entry.metadata().await.map_err(|_| {
@@ -232,17 +277,65 @@ pub async fn get_task_metadata(
         };
 
         files.push(FileMetadata {
-            name: entry.file_name().to_string_lossy().to_string(),
+            sha256: checksums.get(&file_name).cloned(),
+            name: file_name,
             size: meta.len(),
             last_modified,
         });
     }
+    let meta = variant.and_then(|variant| variant.meta.clone());
+    let instructions_html = query.html.then(|| markdown::to_html(&instructions));
     Ok(Json(TaskMetadataResponse {
         instructions,
+        instructions_html,
         files,
+        url: meta.as_ref().and_then(|meta| meta.url.clone()),
+        metadata: meta.map(|meta| meta.metadata).unwrap_or_default(),
     }))
 }
 
+/// The build of the variant from the manifest in its output directory
+fn load_build(output_path: &Path) -> Option<TaskBuildContainer<'static>> {
+    match TaskBuildContainer::from_manifest(&output_path.join(DEFAULT_BUILD_MANIFEST)) {
+        Ok(build) => Some(build),
+        Err(err) => {
+            tracing::warn!("Cannot read the build manifest: {}", err);
+            None
+        }
+    }
+}
+
+/// Checksums of the output files and the package of the variant by their file names
+fn file_checksums(variant: Option<&IntermediateOutput>) -> HashMap<String, String> {
+    let Some(variant) = variant else {
+        return HashMap::new();
+    };
+    variant
+        .outputs
+        .iter()
+        .chain(variant.package.as_ref().map(|package| &package.item))
+        .filter_map(|item| {
+            let name = item.kind.get_filename().file_name()?;
+            Some((name.to_string_lossy().into_owned(), item.sha256.clone()?))
+        })
+        .collect()
+}
+
+/// The manifest, the log and the meta file contain the flags, they are only for debugging on the server
+fn private_files(variant: Option<&IntermediateOutput>) -> Vec<String> {
+    let mut private = vec![
+        DEFAULT_BUILD_MANIFEST.to_string(),
+        DEFAULT_BUILD_LOG.to_string(),
+    ];
+    if let Some(name) = variant
+        .and_then(IntermediateOutput::get_meta)
+        .and_then(|item| item.kind.get_filename().file_name())
+    {
+        private.push(name.to_string_lossy().into_owned());
+    }
+    private
+}
+
 pub async fn download_file_handler(
     AxumPath((course_id, category_name, task_id, user_id, file_name)): AxumPath<(
         String,
@@ -266,7 +359,12 @@ pub async fn download_file_handler(
         .join(&category_name)
         .join(&task_id);
 
-    let output_path = task_root.join("output").join(&user_id).join(&file_name);
+    let variant_path = task_root.join("output").join(&user_id);
+    let build = load_build(&variant_path);
+    if private_files(build.as_ref().and_then(|build| build.outputs.first())).contains(&file_name) {
+        return Err((StatusCode::NOT_FOUND, "File not found".to_string()));
+    }
+    let output_path = variant_path.join(&file_name);
     if !output_path.exists() {
         return Err((StatusCode::NOT_FOUND, "File not found".to_string()));
     }
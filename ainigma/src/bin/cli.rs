@@ -1,13 +1,27 @@
 use ainigma::{
-    build_process::{TaskBuildContainer, build_batch, build_sequential},
+    build_process::{
+        BUILD_MANIFEST_SCHEMA, BuildOptions, TaskBuildContainer, VariantStatus, build_batch,
+        build_sequential, load_output_directory,
+    },
+    cache::BuildCache,
     config::{DEFAULT_BUILD_MANIFEST, ModuleConfiguration, Task, read_check_toml},
     errors::BuildError,
-    moodle::create_exam,
+    events::EventSink,
+    export::{ExportFormat, ctfd},
+    moodle::{
+        AnswerMatching, ExamOptions, create_exam, nested_category,
+        responses::{self, KnownVariant},
+    },
+    reproducible::{check_reproducible_build, check_reproducible_task},
     storages::s3_upload,
+    verify::verify_build,
 };
 use clap::{Args, Parser, Subcommand, crate_description};
 use once_cell::sync::Lazy;
+use progress::PROGRESS;
 use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     process::ExitCode,
     sync::{
@@ -15,15 +29,21 @@ use std::{
         atomic::{AtomicBool, Ordering},
     },
     thread,
+    time::Duration,
 };
 
 use tempfile::TempDir;
 use tokio::runtime::Runtime;
 use uuid::Uuid;
 
+#[path = "cli/progress.rs"]
+mod progress;
+
 // Lazily create a single global Tokio runtime
 static RUNTIME: Lazy<Runtime> =
     Lazy::new(|| Runtime::new().expect("Failed to create Tokio runtime"));
+/// Set by the first Ctrl-C, cancels the running builds
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 /// Autograder CLI Application
 #[derive(Parser, Debug)]
@@ -54,12 +74,53 @@ enum Commands {
         /// The number of build variants to generate
         #[arg(short, long, default_value_t = 1, group = "buildselection")]
         number: usize,
+        /// Build the variants for the given UUIDs instead of new ones. Can be repeated.
+        #[arg(long = "uuid", value_name = "UUID", conflicts_with = "number")]
+        uuids: Vec<Uuid>,
+        /// Always run the builder, even if the build cache has the outputs of an identical build
+        #[arg(long)]
+        no_cache: bool,
+        /// Run the solver of the task against every built variant and fail if any of them cannot be solved
+        #[arg(long)]
+        verify: bool,
+        /// Build every variant a second time with the same UUID and fail if the files differ
+        #[arg(long)]
+        check_reproducible: bool,
+        /// Continue an earlier build in the output directory. The built variants are kept, the unfinished ones
+        /// are built again with the same UUIDs, and new variants are added until there are `--number` of them.
+        #[arg(long, requires = "output_dir")]
+        resume: bool,
+        #[command(flatten)]
+        workers: WorkerOptions,
+    },
+    /// Build variants into a temporary directory and check that the solver of the task recovers their flags
+    #[command(arg_required_else_help = true)]
+    Verify {
+        #[command(flatten)]
+        selection: BuildSelection,
+        /// The number of build variants to verify
+        #[arg(short, long, default_value_t = 1)]
+        number: usize,
+        /// Always run the builder, even if the build cache has the outputs of an identical build
+        #[arg(long)]
+        no_cache: bool,
+        /// Verify the variants of an earlier build in this output directory instead of building new ones
+        #[arg(long, value_name = "DIRECTORY", conflicts_with_all = ["number", "no_cache"])]
+        from: Option<PathBuf>,
+        #[command(flatten)]
+        workers: WorkerOptions,
     },
     /// Attempt to upload previously built files to the cloud storage
     Upload {
         /// Check if the bucket exists
         #[arg(short, long)]
         check_bucket: bool,
+        /// Upload the files of an earlier build in this output directory and store the links in its manifests
+        #[arg(long, value_name = "DIRECTORY", requires = "task")]
+        from: Option<PathBuf>,
+        /// The task whose files are uploaded
+        #[arg(short, long, value_name = "IDENTIFIER")]
+        task: Option<String>,
     },
     /// Check if the configuration has correct syntax and pretty print it
     Validate {
@@ -77,6 +138,58 @@ enum Commands {
         #[command(flatten)]
         selection: BuildSelection,
     },
+    /// Export earlier builds as an import archive of another platform
+    Export {
+        #[command(subcommand)]
+        command: ExportCommand,
+    },
+    /// Map the responses report of a Moodle quiz back to the students and variants, check the flags and report
+    /// suspected flag sharing
+    #[command(arg_required_else_help = true)]
+    Responses {
+        /// Responses report of the quiz, downloaded as CSV
+        #[arg(value_name = "FILE")]
+        report: PathBuf,
+        /// Output directory of the build of the exam
+        #[arg(long, value_name = "DIRECTORY")]
+        from: PathBuf,
+        /// Output file name
+        #[arg(short, long, default_value = "responses.csv")]
+        output: PathBuf,
+    },
+    /// Print the JSON Schema of the build manifest file passed for the builders
+    Schema,
+    /// Manage the build cache. Located in AINIGMA_CACHE_DIR or in the user cache directory.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// Remove cache entries which have not been used recently
+    Prune {
+        /// Remove the entries not used within this many days
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        older_than: u64,
+        /// Remove all the entries
+        #[arg(long, conflicts_with = "older_than")]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ExportCommand {
+    /// ctfcli challenge directories for the tasks built in the directory, installed into CTFd with `ctf challenge install`
+    Ctfd {
+        /// Output directory of an earlier build
+        #[arg(long, value_name = "DIRECTORY")]
+        from: PathBuf,
+        /// Directory of the challenges
+        #[arg(short, long, default_value = "ctfd")]
+        output: PathBuf,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -90,17 +203,42 @@ struct BuildSelection {
     category: Option<usize>,
 }
 
+#[derive(Args, Debug)]
+struct WorkerOptions {
+    /// The number of variants to build at the same time. Defaults to the number of CPUs.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Continue with the other variants when a build fails, instead of cancelling all of them
+    #[arg(long)]
+    keep_going: bool,
+}
+
 #[derive(Debug, Subcommand)]
 enum Moodle {
     Moodle {
         /// Disable automatic upload to the cloud storage
         #[arg(short, long, default_value_t = false)]
         disable_upload: bool,
+        /// Embed the resource files up to this many bytes into the questions instead of uploading them
+        #[arg(long, value_name = "BYTES", requires = "disable_upload")]
+        embed_limit: Option<u64>,
+        /// Category of the questions, which can be nested with `/`
+        #[arg(short, long, required_unless_present = "nested_categories")]
+        category: Option<String>,
+        /// Put the questions into the category `<module name>/<category name>/<task name>` which mirrors the
+        /// configuration
+        #[arg(long, conflicts_with = "category")]
+        nested_categories: bool,
+        /// Output file name, defaults to quiz.xml, quiz.txt, quiz.csv or quiz.zip by the format
         #[arg(short, long)]
-        category: String,
-        /// Output file name
-        #[arg(short, long, default_value = "quiz.xml")]
-        output: String,
+        output: Option<String>,
+        /// Format of the exam: moodle-xml, gift, csv or qti
+        #[arg(long, default_value_t = ExportFormat::MoodleXml)]
+        format: ExportFormat,
+        /// How the answers match the flags: permutations, regexp or regexp-stages. The regexp modes need the regexp
+        /// question type plugin of Moodle
+        #[arg(long, value_name = "MODE", default_value_t = AnswerMatching::Permutations)]
+        answer_matching: AnswerMatching,
     },
 }
 
@@ -194,11 +332,23 @@ fn main() -> std::process::ExitCode {
     // Global stdout subscriber for event tracing, configure based on env var
     let subscriber = tracing_subscriber::FmtSubscriber::builder()
         .with_max_level(log_level)
+        .with_writer(|| progress::LogWriter)
         .finish();
     tracing::subscriber::set_global_default(subscriber).unwrap();
+    let _progress = progress::FinishOnDrop;
 
     let cli = OptsRoot::parse();
 
+    // Schema and cache do not depend on the configuration
+    match &cli.command {
+        Commands::Schema => {
+            println!("{}", BUILD_MANIFEST_SCHEMA.trim_end());
+            return ExitCode::SUCCESS;
+        }
+        Commands::Cache { command } => return cache_command(command),
+        _ => {}
+    }
+
     if !(cli.config.exists()) {
         tracing::error!(
             "Configuration file doesn't exist in path: {:?}",
@@ -221,6 +371,12 @@ fn main() -> std::process::ExitCode {
                 output_dir,
                 selection,
                 number,
+                uuids,
+                no_cache,
+                verify,
+                check_reproducible,
+                resume,
+                workers,
                 moodle,
             } => {
                 let output_dir = match output_dir_selection(output_dir.as_ref()) {
@@ -236,56 +392,105 @@ fn main() -> std::process::ExitCode {
                     Ok(info) => info,
                     Err(code) => return code,
                 };
+                if *check_reproducible
+                    && let Err(error) = check_reproducible_task(validated.task_config)
+                {
+                    tracing::error!("Cannot check the reproducibility: {}", error);
+                    return ExitCode::FAILURE;
+                }
 
-                let outputs = if validated.task_config.batch.is_some() {
-                    tracing::info!(
-                        "Batch mode is enabled for the task '{}', ignoring possible passed variance counts",
-                        validated.task_id.as_ref().unwrap()
-                    );
-                    match build_batch(&config, validated.task_config, output_dir.path(), false) {
-                        Ok(out) => out,
-                        Err(error) => {
-                            tracing::error!(
-                                "Error when building the task in batch mode: {}",
-                                error
-                            );
-                            return ExitCode::FAILURE;
-                        }
+                let previous = if *resume {
+                    match load_previous_build(&config, validated.task_config, output_dir.path()) {
+                        Ok(previous) => Some(previous),
+                        Err(code) => return code,
                     }
                 } else {
-                    tracing::info!(
-                        "Building the task '{}' with the variation count {}",
-                        validated.task_id.as_ref().unwrap(),
-                        number
-                    );
-                    match parallel_task_build(
-                        &config,
-                        validated.task_config,
-                        *number,
-                        output_dir.path(),
-                    ) {
-                        Ok(out) => out,
-                        Err(error) => {
-                            tracing::error!("Error when building the task: {}", error);
-                            return ExitCode::FAILURE;
-                        }
-                    }
+                    None
+                };
+                let uuids = match &previous {
+                    Some(previous) => resumed_uuids(previous, uuids, *number),
+                    None if uuids.is_empty() => (0..*number).map(|_| Uuid::now_v7()).collect(),
+                    None => uuids.clone(),
+                };
+                let options = BuildOptions {
+                    cache: build_cache(*no_cache),
+                    events: EventSink::new(|event| PROGRESS.handle(event)),
+                    ..Default::default()
+                };
+                let (mut outputs, failed) = match build_selected_task(
+                    &config,
+                    &validated,
+                    output_dir.path(),
+                    uuids,
+                    &options,
+                    workers,
+                ) {
+                    Ok(result) => result,
+                    Err(code) => return code,
                 };
+                if let Some(previous) = previous {
+                    let built = previous
+                        .outputs
+                        .into_iter()
+                        .filter(|instance| instance.status == VariantStatus::Built);
+                    outputs.outputs.extend(built);
+                    outputs.outputs.sort_by_key(|instance| instance.uuid);
+                }
+                // With --keep-going, the variants which were built are still used
+                let exit_code = if failed > 0 {
+                    ExitCode::FAILURE
+                } else {
+                    ExitCode::SUCCESS
+                };
+                if *verify && !verify_outputs(&outputs) {
+                    return ExitCode::FAILURE;
+                }
+                if *check_reproducible && !reproducible_outputs(&config, &outputs) {
+                    return ExitCode::FAILURE;
+                }
 
                 match moodle {
                     Some(cmd_moodle) => match cmd_moodle {
                         Moodle::Moodle {
                             category,
+                            nested_categories,
                             output: quiz_filename,
                             disable_upload,
+                            embed_limit,
+                            format,
+                            answer_matching,
                         } => {
                             let results = if outputs.has_files_to_distribute() & !disable_upload {
-                                s3_upload(&config, outputs, &RUNTIME).unwrap()
+                                s3_upload(&config, outputs, &RUNTIME, &options.events).unwrap()
                             } else {
                                 outputs
                             };
-                            let _exam =
-                                create_exam(results, category, quiz_filename, *disable_upload);
+                            let category = if *nested_categories {
+                                nested_category(&config, &results.task)
+                            } else {
+                                category.clone()
+                            };
+                            let Some(category) = category else {
+                                tracing::error!(
+                                    "Task '{}' is not in the configuration",
+                                    results.task.id
+                                );
+                                return ExitCode::FAILURE;
+                            };
+                            let exam_options = ExamOptions {
+                                category,
+                                format: *format,
+                                embed_limit: *embed_limit,
+                                answer_matching: *answer_matching,
+                            };
+                            let quiz_filename = quiz_filename
+                                .clone()
+                                .unwrap_or_else(|| format!("quiz.{}", format.extension()));
+                            if let Err(error) = create_exam(results, &exam_options, &quiz_filename)
+                            {
+                                tracing::error!("Cannot create the exam: {}", error);
+                                return ExitCode::FAILURE;
+                            }
                         }
                     },
                     None => {
@@ -304,14 +509,29 @@ fn main() -> std::process::ExitCode {
                                 );
                             }
                         }
-                        return ExitCode::SUCCESS;
+                        return exit_code;
                     }
                 }
                 // Ensure that possible temporal directory is removed at this point, not earlier
                 // drop(output_dir);
-                ExitCode::SUCCESS
+                exit_code
             }
-            Commands::Upload { check_bucket } => {
+            Commands::Export {
+                command: ExportCommand::Ctfd { from, output },
+            } => export_ctfd(&config, from, output),
+            Commands::Responses {
+                report,
+                from,
+                output,
+            } => check_responses(&config, report, from, output),
+            Commands::Upload {
+                check_bucket,
+                from,
+                task,
+            } => {
+                if let (Some(from), Some(task)) = (from, task) {
+                    return upload_previous_build(&config, from, task);
+                }
                 if *check_bucket {
                     // let result = read_check_toml(cli.config.as_os_str());
                     // match result {
@@ -337,10 +557,85 @@ fn main() -> std::process::ExitCode {
                 }
                 ExitCode::SUCCESS
             }
+            Commands::Verify {
+                selection,
+                number,
+                no_cache,
+                from,
+                workers,
+            } => {
+                let validated = match validate_build_selection(&config, selection) {
+                    Ok(info) => info,
+                    Err(code) => return code,
+                };
+                if validated.task_config.build.solver.is_none() {
+                    tracing::error!(
+                        "Task '{}' has no solver configured",
+                        validated.task_config.id
+                    );
+                    return ExitCode::FAILURE;
+                }
+                if let Some(from) = from {
+                    let mut previous =
+                        match load_previous_build(&config, validated.task_config, from) {
+                            Ok(previous) => previous,
+                            Err(code) => return code,
+                        };
+                    let pending = previous.outputs.len();
+                    previous
+                        .outputs
+                        .retain(|instance| instance.status == VariantStatus::Built);
+                    let pending = pending - previous.outputs.len();
+                    if pending > 0 {
+                        tracing::warn!(
+                            "Skipping {} variants of task '{}' which were not built",
+                            pending,
+                            validated.task_config.id
+                        );
+                    }
+                    return if verify_outputs(&previous) {
+                        ExitCode::SUCCESS
+                    } else {
+                        ExitCode::FAILURE
+                    };
+                }
+                let tempdir = match TempDir::new() {
+                    Ok(dir) => dir,
+                    Err(error) => {
+                        tracing::error!("Cannot create a temporary directory: {}", error);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                let options = BuildOptions {
+                    cache: build_cache(*no_cache),
+                    events: EventSink::new(|event| PROGRESS.handle(event)),
+                    ..Default::default()
+                };
+                let uuids = (0..*number).map(|_| Uuid::now_v7()).collect();
+                let (outputs, failed) = match build_selected_task(
+                    &config,
+                    &validated,
+                    tempdir.path(),
+                    uuids,
+                    &options,
+                    workers,
+                ) {
+                    Ok(result) => result,
+                    Err(code) => return code,
+                };
+                if verify_outputs(&outputs) && failed == 0 {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::FAILURE
+                }
+            }
             Commands::Deploy { .. } => {
                 tracing::error!("Deploy command is not implemented yet.");
                 ExitCode::FAILURE
             }
+            Commands::Schema | Commands::Cache { .. } => {
+                unreachable!("Handled before reading the configuration")
+            }
             Commands::Validate { task } => {
                 tracing::info!("Validating the configuration file...");
                 println!("{config:#?}");
@@ -367,7 +662,13 @@ fn main() -> std::process::ExitCode {
                         "Batch mode is enabled for the task '{}', ignoring possible passed variance counts and defaulting to batch count 1",
                         validated.task_id.as_ref().unwrap()
                     );
-                    match build_batch(&config, validated.task_config, tempdir.path(), true) {
+                    match build_batch(
+                        &config,
+                        validated.task_config,
+                        tempdir.path(),
+                        true,
+                        &BuildOptions::default(),
+                    ) {
                         Ok(out) => out,
                         Err(error) => {
                             tracing::error!(
@@ -390,6 +691,7 @@ fn main() -> std::process::ExitCode {
                         tempdir.path(),
                         1,
                         true,
+                        &BuildOptions::default(),
                     );
                     match output {
                         Ok(out) => TaskBuildContainer::new(
@@ -421,122 +723,480 @@ fn main() -> std::process::ExitCode {
     }
 }
 
-fn parallel_task_build<'a>(
+/// Build the selected task in batch mode if it is configured, otherwise build a variant for each UUID
+///
+/// Returns the build and the number of variants which failed. Failed variants are only accepted with `--keep-going`.
+fn build_selected_task<'a>(
     config: &'a ModuleConfiguration,
-    task_config: &'a Task,
-    number: usize,
+    validated: &ValidatedBuildInfo<'a>,
     output_dir: &'a Path,
-) -> Result<TaskBuildContainer<'a>, BuildError> {
-    let all_outputs = Arc::new(Mutex::new(Vec::with_capacity(number)));
-
-    if number > 1 {
-        let failure_occurred = Arc::new(AtomicBool::new(false));
-        let mut handles = Vec::with_capacity(number);
-        let course_config = Arc::new(config.clone());
-        let task_config = Arc::new(task_config.clone());
-        let output_dir = Arc::new(output_dir.to_path_buf());
-
-        for i in 1..=number {
-            let courseconf = Arc::clone(&course_config);
-            let taskconf = Arc::clone(&task_config);
-            let outputs = Arc::clone(&all_outputs);
-            let outdir = Arc::clone(&output_dir);
-            let failure_flag = Arc::clone(&failure_occurred);
-
-            let handle = thread::spawn(move || {
-                // Check if any thread has already failed
-                if failure_flag.load(Ordering::SeqCst) {
-                    tracing::info!("Skipping variant {} due to failure in another variant", i);
-                    return Err(BuildError::ThreadError(format!(
-                        "Stopping variant {i} due to failure in another thread"
-                    )));
-                }
+    uuids: Vec<Uuid>,
+    options: &BuildOptions,
+    workers: &WorkerOptions,
+) -> Result<(TaskBuildContainer<'a>, usize), ExitCode> {
+    if validated.task_config.batch.is_some() {
+        tracing::info!(
+            "Batch mode is enabled for the task '{}', ignoring possible passed variance counts",
+            validated.task_config.id
+        );
+        build_batch(config, validated.task_config, output_dir, false, options)
+            .map(|build| (build, 0))
+            .map_err(|error| {
+                tracing::error!("Error when building the task in batch mode: {}", error);
+                ExitCode::FAILURE
+            })
+    } else {
+        tracing::info!(
+            "Building the task '{}' with the variation count {}",
+            validated.task_config.id,
+            uuids.len()
+        );
+        let (build, failed) = parallel_task_build(
+            config,
+            validated.task_config,
+            uuids,
+            output_dir,
+            options,
+            workers,
+        );
+        if failed > 0
+            && (!workers.keep_going
+                || build.outputs.is_empty()
+                || INTERRUPTED.load(Ordering::SeqCst))
+        {
+            return Err(ExitCode::FAILURE);
+        }
+        Ok((build, failed))
+    }
+}
 
-                let uuid = Uuid::now_v7();
-                tracing::info!("Starting building the variant {} with UUID {}", i, uuid);
-
-                match build_sequential(&courseconf, &taskconf, uuid, &outdir, i, false) {
-                    Ok(output) => {
-                        let uuid_clone = output.uuid;
-                        outputs
-                            .lock()
-                            .expect("Another thread panicked while owning the mutex when building the task.")
-                            .push(output);
-                        tracing::info!(
-                            "Variant {} building finished with UUID {} when original was {}.",
-                            i,
-                            uuid_clone,
-                            uuid
-                        );
-                        Ok(())
-                    }
-                    Err(error) => {
-                        // Signal that a failure occurred
-                        failure_flag.store(true, Ordering::SeqCst);
-                        tracing::error!("Variant {} failed: {:?}", i, error);
-                        Err(error)
-                    }
-                }
-            });
+/// Load an earlier build of the task from its output directory
+///
+/// The task must still have the same configuration, otherwise the old and new variants would not match.
+fn load_previous_build(
+    config: &ModuleConfiguration,
+    task_config: &Task,
+    output_dir: &Path,
+) -> Result<TaskBuildContainer<'static>, ExitCode> {
+    if task_config.batch.is_some() {
+        tracing::error!(
+            "Task '{}' is built in batch mode, which cannot be resumed",
+            task_config.id
+        );
+        return Err(ExitCode::FAILURE);
+    }
+    let previous = load_output_directory(output_dir, &task_config.id).map_err(|error| {
+        tracing::error!("Cannot load the earlier build: {}", error);
+        ExitCode::FAILURE
+    })?;
+    let task = config.get_task_by_id(&task_config.id);
+    if serde_json::to_value(previous.task.as_ref()).ok() != serde_json::to_value(task).ok() {
+        tracing::error!(
+            "The configuration of task '{}' has changed since the build in '{}'",
+            task_config.id,
+            output_dir.display()
+        );
+        return Err(ExitCode::FAILURE);
+    }
+    Ok(previous)
+}
+
+/// The UUIDs to build when resuming: the unfinished variants, and new ones until there are `number` variants
+///
+/// With explicit UUIDs, only the ones which are not built yet.
+fn resumed_uuids(previous: &TaskBuildContainer, uuids: &[Uuid], number: usize) -> Vec<Uuid> {
+    let built: Vec<Uuid> = previous
+        .outputs
+        .iter()
+        .filter(|instance| instance.status == VariantStatus::Built)
+        .map(|instance| instance.uuid)
+        .collect();
+    if !uuids.is_empty() {
+        return uuids
+            .iter()
+            .filter(|uuid| !built.contains(uuid))
+            .copied()
+            .collect();
+    }
+    let mut resumed: Vec<Uuid> = previous
+        .outputs
+        .iter()
+        .filter(|instance| instance.status == VariantStatus::Pending)
+        .map(|instance| instance.uuid)
+        .collect();
+    let missing = number.saturating_sub(built.len() + resumed.len());
+    resumed.extend((0..missing).map(|_| Uuid::now_v7()));
+    tracing::info!(
+        "Resuming task '{}': {} variants are built and {} will be built",
+        previous.task.id,
+        built.len(),
+        resumed.len()
+    );
+    resumed
+}
+
+/// Upload the files of an earlier build and write the links to its manifests
+fn upload_previous_build(config: &ModuleConfiguration, from: &Path, task_id: &str) -> ExitCode {
+    let Some(task_config) = config.get_task_by_id(task_id) else {
+        tracing::error!("Task ID not found: {task_id}");
+        return ExitCode::FAILURE;
+    };
+    let mut previous = match load_previous_build(config, task_config, from) {
+        Ok(previous) => previous,
+        Err(code) => return code,
+    };
+    previous
+        .outputs
+        .retain(|instance| instance.status == VariantStatus::Built);
+    if !previous.has_files_to_distribute() {
+        tracing::info!("Task '{}' has no files to upload", task_id);
+        return ExitCode::SUCCESS;
+    }
+    let events = EventSink::new(|event| PROGRESS.handle(event));
+    let uploaded = match s3_upload(config, previous, &RUNTIME, &events) {
+        Ok(uploaded) => uploaded,
+        Err(error) => {
+            tracing::error!("Error when uploading the files: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(error) = uploaded.write_manifests() {
+        tracing::error!("Cannot store the links of the uploaded files: {}", error);
+        return ExitCode::FAILURE;
+    }
+    tracing::info!(
+        "Uploaded the files of {} variants of task '{}'",
+        uploaded.outputs.len(),
+        task_id
+    );
+    ExitCode::SUCCESS
+}
 
-            handles.push(handle);
+/// Load the builds of all the tasks in an earlier output directory, skipping the tasks which were not built there
+fn load_builds(config: &ModuleConfiguration, from: &Path) -> Vec<TaskBuildContainer<'static>> {
+    let mut builds = Vec::new();
+    for category in &config.categories {
+        for task in &category.tasks {
+            match load_output_directory(from, &task.id) {
+                Ok(build) => builds.push(build),
+                Err(error) => tracing::warn!("Task '{}' is skipped: {}", task.id, error),
+            }
         }
+    }
+    builds
+}
 
-        // Join threads and collect results
-        let mut first_error = None;
+/// Export the tasks built in the output directory as ctfcli challenges
+fn export_ctfd(config: &ModuleConfiguration, from: &Path, output: &Path) -> ExitCode {
+    let builds = load_builds(config, from);
+    match ctfd::write_challenges(config, &builds, output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            tracing::error!("Cannot export the CTFd challenges: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-        for handle in handles {
-            match handle.join() {
-                Ok(thread_result) => {
-                    if let Err(e) = thread_result {
-                        // Save the first error we encounter
-                        if first_error.is_none() {
-                            first_error = Some(e);
-                        }
-                    }
+fn check_responses(
+    config: &ModuleConfiguration,
+    report: &Path,
+    from: &Path,
+    output: &Path,
+) -> ExitCode {
+    let variants = KnownVariant::from_builds(&load_builds(config, from));
+    if variants.is_empty() {
+        tracing::error!("There are no built variants in '{}'", from.display());
+        return ExitCode::FAILURE;
+    }
+    let attempts = match std::fs::File::open(report)
+        .map_err(csv::Error::from)
+        .and_then(responses::read_responses)
+    {
+        Ok(attempts) => attempts,
+        Err(error) => {
+            tracing::error!(
+                "Cannot read the responses '{}': {}",
+                report.display(),
+                error
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let reports = responses::report(&attempts, &variants, &config.flag_config.user_derived);
+    if let Err(error) = std::fs::File::create(output)
+        .map_err(csv::Error::from)
+        .and_then(|file| responses::write_report(&reports, file))
+    {
+        tracing::error!("Cannot write the report '{}': {}", output.display(), error);
+        return ExitCode::FAILURE;
+    }
+    let suspicious = reports
+        .iter()
+        .filter(|report| !report.suspicions.is_empty())
+        .count();
+    if suspicious > 0 {
+        tracing::warn!(
+            "{} of {} responses are suspicious",
+            suspicious,
+            reports.len()
+        );
+    }
+    let unlisted = reports
+        .iter()
+        .filter(|report| !report.unlisted_flags.is_empty())
+        .count();
+    if unlisted > 0 {
+        tracing::warn!(
+            "{} of {} responses have correct flags of variants which are not in '{}'",
+            unlisted,
+            reports.len(),
+            from.display()
+        );
+    }
+    tracing::info!(
+        "Mapped {} responses of {} attempts to {} variants into '{}'",
+        reports.len(),
+        attempts.len(),
+        variants.len(),
+        output.display()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Run the solver for all the variants, returns true if all of them were solved
+fn verify_outputs(outputs: &TaskBuildContainer) -> bool {
+    match verify_build(outputs) {
+        Ok(failures) if failures.is_empty() => {
+            tracing::info!(
+                "All {} variants of task '{}' were solved successfully",
+                outputs.outputs.len(),
+                outputs.task.id
+            );
+            true
+        }
+        Ok(failures) => {
+            tracing::error!(
+                "{} of {} variants of task '{}' failed verification",
+                failures.len(),
+                outputs.outputs.len(),
+                outputs.task.id
+            );
+            false
+        }
+        Err(error) => {
+            tracing::error!("Cannot verify the task: {}", error);
+            false
+        }
+    }
+}
+
+/// Build the variants again and compare them, returns true if all of them were identical
+fn reproducible_outputs(config: &ModuleConfiguration, outputs: &TaskBuildContainer) -> bool {
+    let tempdir = match tempfile::tempdir() {
+        Ok(dir) => dir,
+        Err(error) => {
+            tracing::error!("Cannot create a directory for the second build: {}", error);
+            return false;
+        }
+    };
+    match check_reproducible_build(config, outputs, tempdir.path()) {
+        Ok(differences) if differences.is_empty() => {
+            tracing::info!(
+                "All {} variants of task '{}' were reproducible",
+                outputs.outputs.len(),
+                outputs.task.id
+            );
+            true
+        }
+        Ok(differences) => {
+            tracing::error!(
+                "Task '{}' is not reproducible, {} files or flags differ between the builds",
+                outputs.task.id,
+                differences.len()
+            );
+            false
+        }
+        Err(error) => {
+            tracing::error!("Cannot check the reproducibility: {}", error);
+            false
+        }
+    }
+}
+
+/// Build cache in the default location, unless disabled
+fn build_cache(no_cache: bool) -> Option<BuildCache> {
+    if no_cache {
+        return None;
+    }
+    let cache = BuildCache::default_location().map(BuildCache::new);
+    if cache.is_none() {
+        tracing::warn!(
+            "Cannot find a location for the build cache, set AINIGMA_CACHE_DIR to use it"
+        );
+    }
+    cache
+}
+
+fn cache_command(command: &CacheCommand) -> ExitCode {
+    let Some(cache) = build_cache(false) else {
+        return ExitCode::FAILURE;
+    };
+    match command {
+        CacheCommand::Prune { older_than, all } => {
+            let older_than = (!all).then(|| Duration::from_secs(older_than * 24 * 60 * 60));
+            match cache.prune(older_than) {
+                Ok(removed) => {
+                    tracing::info!(
+                        "Removed {} entries from the build cache in '{}'",
+                        removed,
+                        cache.root().display()
+                    );
+                    ExitCode::SUCCESS
                 }
-                Err(panic_error) => {
-                    let error_msg = format!("Thread panicked: {panic_error:?}");
-                    tracing::error!("{}", error_msg);
-
-                    if first_error.is_none() {
-                        first_error = Some(BuildError::ThreadError(format!(
-                            "Error when joining thread: {error_msg}"
-                        )));
-                    }
+                Err(error) => {
+                    tracing::error!("Error when pruning the build cache: {}", error);
+                    ExitCode::FAILURE
                 }
             }
         }
+    }
+}
 
-        // If any thread had an error, return it
-        if let Some(error) = first_error {
-            return Err(error);
+/// Build a variant for each UUID with a pool of worker threads
+///
+/// Unless `--keep-going` is given, the first failure cancels the running builds and the remaining variants.
+/// Returns the built variants and the number of variants which failed or were cancelled.
+fn parallel_task_build<'a>(
+    config: &'a ModuleConfiguration,
+    task_config: &'a Task,
+    uuids: Vec<Uuid>,
+    output_dir: &'a Path,
+    options: &BuildOptions,
+    workers: &WorkerOptions,
+) -> (TaskBuildContainer<'a>, usize) {
+    let number = uuids.len();
+    let jobs = workers
+        .jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+        .clamp(1, number.max(1));
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = BuildOptions {
+        cancel: Some(Arc::clone(&cancel)),
+        ..options.clone()
+    };
+    let queue = Mutex::new((1..=number).zip(uuids).collect::<VecDeque<_>>());
+    let results = Mutex::new(Vec::with_capacity(number));
+    handle_interrupts();
+    PROGRESS.start(&task_config.id, number);
+    tracing::debug!("Building {} variants with {} workers", number, jobs);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    while !cancel.load(Ordering::SeqCst) {
+                        let Some((i, uuid)) = queue.lock().unwrap().pop_front() else {
+                            break;
+                        };
+                        tracing::info!("Starting building the variant {} with UUID {}", i, uuid);
+                        let result = build_sequential(
+                            config,
+                            task_config,
+                            uuid,
+                            output_dir,
+                            i,
+                            false,
+                            &options,
+                        );
+                        match &result {
+                            Ok(_) => {
+                                tracing::info!("Variant {} with UUID {} finished building", i, uuid)
+                            }
+                            Err(BuildError::Cancelled(_)) => {
+                                tracing::info!("Variant {} with UUID {} was cancelled", i, uuid)
+                            }
+                            Err(error) => {
+                                tracing::error!(
+                                    "Variant {} with UUID {} failed: {}",
+                                    i,
+                                    uuid,
+                                    error
+                                );
+                                if !workers.keep_going {
+                                    cancel.store(true, Ordering::SeqCst);
+                                }
+                            }
+                        }
+                        results.lock().unwrap().push((i, uuid, result));
+                    }
+                })
+            })
+            .collect();
+        // Forward Ctrl-C to the builds until all the workers have finished
+        while !handles.iter().all(|handle| handle.is_finished()) {
+            if INTERRUPTED.load(Ordering::SeqCst) && !cancel.swap(true, Ordering::SeqCst) {
+                tracing::warn!("Interrupted, cancelling the running builds");
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+
+    let mut results = results.into_inner().expect("A build worker panicked");
+    results.sort_by_key(|(i, _, _)| *i);
+    let not_started = queue.into_inner().expect("A build worker panicked").len();
+    let mut outputs = Vec::with_capacity(results.len());
+    let mut failures = Vec::new();
+    let mut cancelled = not_started;
+    for (i, uuid, result) in results {
+        match result {
+            Ok(output) => outputs.push(output),
+            Err(BuildError::Cancelled(_)) => cancelled += 1,
+            Err(error) => failures.push((i, uuid, error)),
         }
+    }
+
+    if failures.is_empty() && cancelled == 0 {
+        tracing::info!(
+            "All {} variants of task '{}' were built successfully",
+            number,
+            task_config.id
+        );
     } else {
-        // Single variant case, no threading needed
-        let uuid = Uuid::now_v7();
-        match build_sequential(config, task_config, uuid, output_dir, 1, false) {
-            Ok(outputs) => {
-                all_outputs.lock().unwrap().push(outputs);
-                tracing::info!("Task '{}' build successfully", task_config.id);
-            }
-            Err(error) => {
-                return Err(error);
-            }
+        tracing::error!(
+            "Built {} of {} variants of task '{}': {} failed and {} were cancelled",
+            outputs.len(),
+            number,
+            task_config.id,
+            failures.len(),
+            cancelled
+        );
+        for (i, uuid, error) in &failures {
+            tracing::error!("Variant {} with UUID {} failed: {}", i, uuid, error);
         }
     }
+    let failed = failures.len() + cancelled;
+    (
+        TaskBuildContainer::new(output_dir.to_path_buf(), task_config, outputs, false),
+        failed,
+    )
+}
 
-    let vec = Arc::try_unwrap(all_outputs)
-        .expect("There are still other Arc references when there should not")
-        .into_inner()
-        .expect("Mutex cannot be locked");
-
-    Ok(TaskBuildContainer::new(
-        output_dir.to_path_buf(),
-        task_config,
-        vec,
-        false,
-    ))
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// The builders run in their own process groups and do not receive Ctrl-C from the terminal.
+/// The first Ctrl-C cancels the builds, and the second one terminates immediately.
+fn handle_interrupts() {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
 }
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,148 @@
+//! Progress line of the builds, drawn below the log lines when stderr is a terminal
+use ainigma::events::BuildEvent;
+use std::io::{IsTerminal, Write};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+pub static PROGRESS: Progress = Progress::new();
+
+pub struct Progress {
+    state: Mutex<State>,
+}
+
+struct State {
+    enabled: bool,
+    visible: bool,
+    task_id: String,
+    total: usize,
+    started: usize,
+    built: usize,
+    cached: usize,
+    failed: usize,
+    uploaded: usize,
+}
+
+impl Progress {
+    const fn new() -> Self {
+        Self {
+            state: Mutex::new(State::new()),
+        }
+    }
+
+    /// Start showing the progress of building `total` variants of the task
+    pub fn start(&self, task_id: &str, total: usize) {
+        let mut state = self.lock();
+        state.clear();
+        *state = State {
+            enabled: std::io::stderr().is_terminal(),
+            task_id: task_id.to_string(),
+            total,
+            ..State::new()
+        };
+        state.draw();
+    }
+
+    pub fn handle(&self, event: &BuildEvent) {
+        let mut state = self.lock();
+        match event {
+            BuildEvent::VariantStarted { .. } => state.started += 1,
+            BuildEvent::VariantFinished { cached, .. } => {
+                state.built += 1;
+                state.cached += usize::from(*cached);
+            }
+            BuildEvent::Failed { .. } => state.failed += 1,
+            BuildEvent::UploadFinished { .. } => state.uploaded += 1,
+            BuildEvent::BuilderOutput { .. } | BuildEvent::OutputsValidated { .. } => return,
+        }
+        state.draw();
+    }
+
+    /// Remove the progress line
+    pub fn finish(&self) {
+        let mut state = self.lock();
+        state.clear();
+        state.enabled = false;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            enabled: false,
+            visible: false,
+            task_id: String::new(),
+            total: 0,
+            started: 0,
+            built: 0,
+            cached: 0,
+            failed: 0,
+            uploaded: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        if self.visible {
+            eprint!("\r\x1b[2K");
+            self.visible = false;
+        }
+    }
+
+    fn draw(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        let mut line = format!(
+            "{}: {}/{} variants built",
+            self.task_id, self.built, self.total
+        );
+        let running = self.started.saturating_sub(self.built + self.failed);
+        for (count, label) in [
+            (running, "running"),
+            (self.cached, "from cache"),
+            (self.failed, "failed"),
+            (self.uploaded, "uploaded"),
+        ] {
+            if count > 0 {
+                line.push_str(&format!(", {count} {label}"));
+            }
+        }
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+        self.visible = true;
+    }
+}
+
+/// Removes the progress line when the command exits
+pub struct FinishOnDrop;
+
+impl Drop for FinishOnDrop {
+    fn drop(&mut self) {
+        PROGRESS.finish();
+    }
+}
+
+/// Writer for the log lines, which keeps the progress line below them
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = PROGRESS.lock();
+        let visible = state.visible;
+        state.clear();
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(buf)?;
+        stdout.flush()?;
+        if visible {
+            state.draw();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+}
@@ -1,23 +1,60 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use tokio::process::Command as TokioCommand;
+use std::process::{Child, Output, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 // use tracing::instrument;
 use uuid::Uuid;
 
+use crate::cache::{BuildCache, CacheKey};
+use crate::checksum::sha256_file;
 use crate::config::{
-    BuildConfig, Builder, DEFAULT_BUILD_MANIFEST, DEFAULT_FLAGS_FILENAME, FlagVariantKind,
-    ModuleConfiguration, OutputKind, Task,
+    BuildConfig, Builder, Container, DEFAULT_BUILD_LOG, DEFAULT_BUILD_MANIFEST,
+    DEFAULT_FLAGS_FILENAME, FlagVariantKind, ModuleConfiguration, OutputKind, Shell, Task,
 };
+use crate::container::container_command;
 use crate::errors::BuildError;
+use crate::events::{BuildEvent, EventSink};
 use crate::flag_generator::Flag;
+use crate::leak_scan::check_leaks;
+use crate::package::{Package, package_instance};
+use crate::sandbox::sandboxed_command;
+use crate::template;
+
+/// Version of the `build-manifest.json` format, see [`BUILD_MANIFEST_SCHEMA`]
+pub const BUILD_MANIFEST_SCHEMA_VERSION: u32 = 1;
+/// JSON Schema of the `build-manifest.json` file, for builders that want to validate the manifest
+pub const BUILD_MANIFEST_SCHEMA: &str = include_str!("../schemas/build-manifest-v1.json");
+/// Interval for checking the cancel flag while a builder is running
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Time a cancelled builder gets to exit after SIGTERM before it is killed
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Options for running the builders, shared by the build modes
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Reuse the outputs of earlier builds with identical inputs
+    pub cache: Option<BuildCache>,
+    /// When set to true, the running builder is terminated and the build fails with [`BuildError::Cancelled`]
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Receives the progress of the builds
+    pub events: EventSink,
+}
 
 /// Represents the build process of a task, including the initial configuration and produced output files and flags.
-#[derive(serde::Serialize, Debug)]
+///
+/// Serialized as the build manifest. A container loaded from a manifest owns its task.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct TaskBuildContainer<'a> {
+    schema_version: u32,
     pub basedir: PathBuf,
-    pub task: &'a Task,
+    pub task: Cow<'a, Task>,
     /// For batch mode, this is > 1, for a sequential build, this is 1
     pub outputs: Vec<IntermediateOutput>,
     batched: bool,
@@ -30,15 +67,75 @@ impl<'a> TaskBuildContainer<'a> {
         batched: bool,
     ) -> Self {
         Self {
+            schema_version: BUILD_MANIFEST_SCHEMA_VERSION,
             basedir: out_dir,
-            task,
+            task: Cow::Borrowed(task),
             outputs,
             batched,
         }
     }
 }
 
+impl TaskBuildContainer<'static> {
+    /// Load a build manifest written by an earlier build
+    pub fn from_manifest(path: &Path) -> Result<Self, BuildError> {
+        let file = fs::File::open(path).map_err(|e| {
+            BuildError::ManifestError(format!("Cannot open '{}': {}", path.display(), e))
+        })?;
+        let value: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| {
+                BuildError::ManifestError(format!("Cannot parse '{}': {}", path.display(), e))
+            })?;
+        // The version is checked first, as other versions may not deserialize at all
+        let version = value.get("schema_version").and_then(|v| v.as_u64());
+        if version != Some(BUILD_MANIFEST_SCHEMA_VERSION.into()) {
+            return Err(BuildError::ManifestError(format!(
+                "'{}' has schema version {}, only version {} is supported",
+                path.display(),
+                version.map_or("none".to_string(), |v| v.to_string()),
+                BUILD_MANIFEST_SCHEMA_VERSION
+            )));
+        }
+        serde_json::from_value(value).map_err(|e| {
+            BuildError::ManifestError(format!("Invalid manifest '{}': {}", path.display(), e))
+        })
+    }
+}
+
 impl TaskBuildContainer<'_> {
+    pub fn is_batched(&self) -> bool {
+        self.batched
+    }
+
+    /// Write the manifest to the given path
+    pub fn write_manifest(&self, path: &Path) -> Result<(), BuildError> {
+        let file = fs::File::create(path).map_err(|e| {
+            BuildError::ManifestError(format!("Cannot create '{}': {}", path.display(), e))
+        })?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)
+            .map_err(|e| BuildError::SerdeDerserializationFailed(e.to_string()))
+    }
+
+    /// Write the manifests back to their places in the output directory, e.g. after the files were uploaded
+    ///
+    /// A batch build has a single manifest in the base directory, otherwise each variant has its own.
+    pub fn write_manifests(&self) -> Result<(), BuildError> {
+        if self.batched {
+            return self.write_manifest(&self.basedir.join(DEFAULT_BUILD_MANIFEST));
+        }
+        for instance in &self.outputs {
+            let single = TaskBuildContainer {
+                schema_version: BUILD_MANIFEST_SCHEMA_VERSION,
+                basedir: self.basedir.clone(),
+                task: Cow::Borrowed(self.task.as_ref()),
+                outputs: vec![instance.clone()],
+                batched: false,
+            };
+            single.write_manifest(&instance.task_instance_dir.join(DEFAULT_BUILD_MANIFEST))?;
+        }
+        Ok(())
+    }
+
     pub fn validate_output(&mut self) -> Result<(), BuildError> {
         for intermediate in &mut self.outputs {
             for item in &mut intermediate.outputs {
@@ -58,6 +155,13 @@ impl TaskBuildContainer<'_> {
                         return Err(BuildError::OutputVerificationFailed(e.to_string()));
                     }
                 };
+                item.update_integrity(&path).map_err(|e| {
+                    BuildError::OutputVerificationFailed(format!(
+                        "Cannot compute the checksum of '{}': {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
                 item.update_path(path);
             }
         }
@@ -71,13 +175,34 @@ impl TaskBuildContainer<'_> {
     }
 }
 
+/// Whether the outputs of a variant are ready
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantStatus {
+    /// The builder has not finished successfully, the manifest given to the builder has this status
+    #[default]
+    Pending,
+    /// The outputs were validated and the flags collected
+    Built,
+}
+
 // All flags in a single task's stages
-#[derive(serde::Serialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct IntermediateOutput {
     pub uuid: Uuid,
     pub stage_flags: Vec<Flag>,
     pub task_instance_dir: PathBuf,
     pub outputs: Vec<OutputItem>,
+    /// Log of the builder run, see [`DEFAULT_BUILD_LOG`]. Not set when only validating.
+    pub build_log: Option<PathBuf>,
+    #[serde(default)]
+    pub status: VariantStatus,
+    /// Details from the meta file of the builder, if the task has a `meta` output
+    #[serde(default)]
+    pub meta: Option<Challenge>,
+    /// Archive of the resource files, if the task packages them
+    #[serde(default)]
+    pub package: Option<Package>,
 }
 
 impl IntermediateOutput {
@@ -92,6 +217,10 @@ impl IntermediateOutput {
             stage_flags,
             task_instance_dir,
             outputs,
+            build_log: None,
+            status: VariantStatus::Pending,
+            meta: None,
+            package: None,
         }
     }
     pub fn validate_readme_count(&self) -> Result<(), BuildError> {
@@ -118,6 +247,19 @@ impl IntermediateOutput {
             })
             .collect()
     }
+    /// Files uploaded for the end-user: the package if there is one, otherwise the resource files
+    pub fn get_distributed_files(&self) -> Vec<OutputItem> {
+        match &self.package {
+            Some(package) => vec![package.item.clone()],
+            None => self.get_resource_files(),
+        }
+    }
+    /// Get the meta file from the output files
+    pub fn get_meta(&self) -> Option<&OutputItem> {
+        self.outputs
+            .iter()
+            .find(|output| matches!(output.kind, OutputKind::Meta(_)))
+    }
     /// Get readme.txt from the output files
     pub fn get_readme(&self) -> Option<&OutputItem> {
         self.outputs
@@ -127,7 +269,11 @@ impl IntermediateOutput {
     /// Update common files that apply to all flag entries
     pub fn update_files(&mut self, items: Vec<OutputItem>) {
         for item in items {
-            if let Some(index) = self.outputs.iter().position(|x| x.kind == item.kind) {
+            if let Some(package) = &mut self.package
+                && package.item.kind == item.kind
+            {
+                package.item = item;
+            } else if let Some(index) = self.outputs.iter().position(|x| x.kind == item.kind) {
                 self.outputs[index] = item;
             } else {
                 self.outputs.push(item);
@@ -136,51 +282,82 @@ impl IntermediateOutput {
     }
 }
 
-/// Build process can return metadata about the task
+/// Build process can return metadata about the task with the `meta` output, see [`OutputKind::Meta`]
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct Meta {
+pub struct Meta {
+    /// ID of the built task
     pub task: String,
     pub challenges: Vec<Challenge>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct Challenge {
+/// Details of a single variant reported by the builder
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Challenge {
     pub uuid: Uuid,
-    pub flag: String,
+    /// Flag of the variant, must be one of the flags given to the builder
+    #[serde(default)]
+    pub flag: Option<String>,
+    /// Address of an external resource of the variant, such as a deployed challenge instance
+    #[serde(default)]
     pub url: Option<String>,
+    /// Other details which are shown to the student
+    #[serde(default)]
+    pub metadata: BTreeMap<String, serde_json::Value>,
+}
+
+impl Meta {
+    /// Read the meta file written by the builder
+    pub fn from_file(path: &Path) -> Result<Self, BuildError> {
+        let file = fs::File::open(path).map_err(|e| {
+            BuildError::OutputVerificationFailed(format!(
+                "Cannot open the meta file '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        serde_json::from_reader(std::io::BufReader::new(file)).map_err(|e| {
+            BuildError::OutputVerificationFailed(format!(
+                "Invalid meta file '{}': {}",
+                path.display(),
+                e
+            ))
+        })
+    }
 }
 
 fn create_flags_by_task<'a>(
     task_config: &'a Task,
     module_config: &'a ModuleConfiguration,
     uuid: Uuid,
-) -> Vec<Flag> {
+) -> Result<Vec<Flag>, BuildError> {
+    let user_derived = &module_config.flag_config.user_derived;
     let mut flags = Vec::with_capacity(task_config.stages.len());
     for stage in &task_config.stages {
         // Get ID from stage or fall back to task ID
         let id = stage.id.as_deref().unwrap_or(&task_config.id);
         let flag = match stage.flag.kind {
-            FlagVariantKind::UserDerived => Flag::new_user_flag(
-                id.into(),
-                &module_config.flag_config.user_derived.algorithm,
-                &module_config.flag_config.user_derived.secret,
-                id,
-                &uuid,
-            ),
             FlagVariantKind::PureRandom => {
                 Flag::new_random_flag(id.into(), module_config.flag_config.pure_random.length)
             }
-            FlagVariantKind::RngSeed => Flag::new_rng_seed(
-                id.into(),
-                &module_config.flag_config.user_derived.algorithm,
-                &module_config.flag_config.user_derived.secret,
-                id,
-                &uuid,
-            ),
+            FlagVariantKind::UserDerived | FlagVariantKind::RngSeed => {
+                let secret = user_derived
+                    .active_secret()
+                    .and_then(|secret| secret.value())
+                    .ok_or_else(|| {
+                        BuildError::FlagSecretError(format!(
+                            "No resolved secret available for the flag of stage {id}"
+                        ))
+                    })?;
+                if stage.flag.kind == FlagVariantKind::UserDerived {
+                    Flag::new_user_flag(id.into(), &user_derived.algorithm, secret, id, &uuid)
+                } else {
+                    Flag::new_rng_seed(id.into(), &user_derived.algorithm, secret, id, &uuid)
+                }
+            }
         };
         flags.push(flag);
     }
-    flags
+    Ok(flags)
 }
 
 #[allow(dead_code)]
@@ -204,11 +381,28 @@ fn get_build_info(
 pub struct OutputItem {
     pub kind: OutputKind,
     pub link: Option<String>,
+    /// SHA-256 of the file as lowercase hex, set when the outputs are validated
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Size of the file in bytes, set when the outputs are validated
+    #[serde(default)]
+    pub size: Option<u64>,
 }
 
 impl OutputItem {
     pub fn new(kind: OutputKind) -> Self {
-        Self { kind, link: None }
+        Self {
+            kind,
+            link: None,
+            sha256: None,
+            size: None,
+        }
+    }
+    /// Record the checksum and the size of the file, so it can later be shown which file was distributed
+    pub fn update_integrity(&mut self, path: &Path) -> std::io::Result<()> {
+        self.size = Some(fs::metadata(path)?.len());
+        self.sha256 = Some(sha256_file(path)?);
+        Ok(())
     }
     pub fn set_link(&mut self, link: String) {
         self.link = Some(link);
@@ -241,52 +435,453 @@ fn verify_output_dir(
     }
 }
 
+/// Environment variables passed for the builder in addition to the build manifest.
+/// With a single task instance, the flags of each stage and the UUID are exported as well.
+fn build_environment(
+    manifest_path: &Path,
+    task_id: &str,
+    output_dir: &Path,
+    instance: Option<&IntermediateOutput>,
+) -> HashMap<String, String> {
+    let mut envs = HashMap::from([
+        (
+            "BUILD_MANIFEST".to_string(),
+            manifest_path.to_str().unwrap_or_default().to_string(),
+        ),
+        ("TASK_ID".to_string(), task_id.to_string()),
+        (
+            "OUTPUT_DIR".to_string(),
+            output_dir.to_str().unwrap_or_default().to_string(),
+        ),
+    ]);
+    if let Some(instance) = instance {
+        envs.insert("TASK_UUID".to_string(), instance.uuid.to_string());
+        envs.extend(
+            instance
+                .stage_flags
+                .iter()
+                .map(|flag| flag.get_flag_type_value_pair()),
+        );
+    }
+    envs
+}
+
+/// Command for running the builder in the task directory.
+/// Container builders run inside their image, other builders inside a sandbox if the task configures one.
+fn builder_command(
+    program: &str,
+    args: &[&str],
+    task_directory: &Path,
+    build_manifest: &TaskBuildContainer,
+    build_envs: HashMap<String, String>,
+) -> Result<std::process::Command, BuildError> {
+    let build = &build_manifest.task.build;
+    match (&build.builder, &build.sandbox) {
+        (Builder::Container(container), sandbox) => container_command(
+            container,
+            sandbox.as_ref(),
+            program,
+            args,
+            task_directory,
+            &writable_dirs(build_manifest),
+            build_envs,
+        ),
+        (_, Some(sandbox)) => sandboxed_command(
+            sandbox,
+            program,
+            args,
+            task_directory,
+            &writable_dirs(build_manifest),
+            build_envs,
+        ),
+        (_, None) => {
+            let mut command = std::process::Command::new(program);
+            command
+                .args(args)
+                .envs(build_envs) // Use merged environment instead of env_clear()
+                .current_dir(task_directory);
+            Ok(command)
+        }
+    }
+}
+
+/// Batch builder writes all the variants, otherwise only the instance directory is needed
+fn writable_dirs(build_manifest: &TaskBuildContainer) -> Vec<PathBuf> {
+    if build_manifest.batched {
+        vec![build_manifest.basedir.clone()]
+    } else {
+        build_manifest
+            .outputs
+            .iter()
+            .map(|output| output.task_instance_dir.clone())
+            .collect()
+    }
+}
+
+fn spawn_error(build_manifest: &TaskBuildContainer, error: std::io::Error) -> BuildError {
+    let build = &build_manifest.task.build;
+    if error.kind() == std::io::ErrorKind::NotFound {
+        if let Builder::Container(container) = &build.builder {
+            return BuildError::ShellSubprocessError(format!(
+                "Could not start the container for task {}, is {} installed? {}",
+                build_manifest.task.id,
+                container.engine.program(),
+                error
+            ));
+        } else if build.sandbox.is_some() {
+            return BuildError::SandboxError(format!(
+                "Could not start the sandbox for task {}, is bubblewrap (bwrap) installed? {}",
+                build_manifest.task.id, error
+            ));
+        }
+    }
+    BuildError::ShellSubprocessError(format!(
+        "The build process of task {} failed prematurely: {}",
+        build_manifest.task.id, error
+    ))
+}
+
+/// Verify the output files of the builder, read the flags it has produced and check them for leaks
+///
+/// Runs for fresh and cached builds alike, so the readme template is checked here too.
+fn collect_outputs(
+    build_manifest: &mut TaskBuildContainer,
+    task_directory: &Path,
+) -> Result<(), BuildError> {
+    build_manifest.validate_output()?;
+
+    // If the task has a seed-based flag, we must capture the resulting flag from the process output
+    // Stored into the file flags.json by default, using same key as the passed environment variable
+    map_rng_seed_to_flag(
+        &mut build_manifest.outputs,
+        &build_manifest.basedir,
+        &build_manifest.task,
+    )?;
+    merge_meta(build_manifest)?;
+    // Leaked flags must be found before the files are uploaded
+    check_leaks(build_manifest)?;
+    check_readme_template(build_manifest, task_directory)?;
+    if let Some(package) = &build_manifest.task.build.package {
+        for instance in &mut build_manifest.outputs {
+            package_instance(package, &build_manifest.task.id, instance)?;
+        }
+    }
+    for instance in &mut build_manifest.outputs {
+        instance.status = VariantStatus::Built;
+    }
+    Ok(())
+}
+
+/// Read the meta files of the variants, if the task has a `meta` output
+///
+/// Each variant must be listed in its meta file, and a reported flag must be one of the flags of the variant.
+fn merge_meta(build_manifest: &mut TaskBuildContainer) -> Result<(), BuildError> {
+    let task_id = &build_manifest.task.id;
+    for instance in &mut build_manifest.outputs {
+        let Some(item) = instance.get_meta() else {
+            continue;
+        };
+        let path = instance.task_instance_dir.join(item.kind.get_filename());
+        let meta = Meta::from_file(&path)?;
+        if &meta.task != task_id {
+            return Err(BuildError::OutputVerificationFailed(format!(
+                "The meta file '{}' is for task {}, expected {}",
+                path.display(),
+                meta.task,
+                task_id
+            )));
+        }
+        let Some(challenge) = meta
+            .challenges
+            .into_iter()
+            .find(|challenge| challenge.uuid == instance.uuid)
+        else {
+            return Err(BuildError::OutputVerificationFailed(format!(
+                "The meta file '{}' does not have the variant {}",
+                path.display(),
+                instance.uuid
+            )));
+        };
+        if let Some(flag) = &challenge.flag
+            && !instance
+                .stage_flags
+                .iter()
+                .any(|known| known.flag_string() == *flag || known.encased() == flag)
+        {
+            return Err(BuildError::FlagCollectionError(format!(
+                "The meta file of task {} reports a flag for variant {} which was not given to the builder",
+                task_id, instance.uuid
+            )));
+        }
+        instance.meta = Some(challenge);
+    }
+    Ok(())
+}
+
+/// Render the readme template of the task for the variants, so mistakes in it fail the build
+fn check_readme_template(
+    build_manifest: &TaskBuildContainer,
+    task_directory: &Path,
+) -> Result<(), BuildError> {
+    if build_manifest.task.build.readme_template.is_none() {
+        return Ok(());
+    }
+    for instance in &build_manifest.outputs {
+        template::instructions(&build_manifest.task, task_directory, instance)?;
+    }
+    Ok(())
+}
+
+/// Load the variants of the task from an output directory of an earlier build
+///
+/// Batch builds have their manifest in the output directory, sequential builds in the variant directories.
+/// Variants which did not finish have the status [`VariantStatus::Pending`].
+pub fn load_output_directory(
+    output_directory: &Path,
+    task_id: &str,
+) -> Result<TaskBuildContainer<'static>, BuildError> {
+    let batch_manifest = output_directory.join(DEFAULT_BUILD_MANIFEST);
+    if batch_manifest.exists() {
+        let build = TaskBuildContainer::from_manifest(&batch_manifest)?;
+        if build.batched && build.task.id == task_id {
+            return Ok(build);
+        }
+    }
+    let entries = fs::read_dir(output_directory).map_err(|e| {
+        BuildError::ManifestError(format!(
+            "Cannot read the output directory '{}': {}",
+            output_directory.display(),
+            e
+        ))
+    })?;
+    let mut task = None;
+    let mut outputs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path().join(task_id).join(DEFAULT_BUILD_MANIFEST);
+        if !path.is_file() {
+            continue;
+        }
+        let build = TaskBuildContainer::from_manifest(&path)?;
+        if build.task.id != task_id || build.batched {
+            return Err(BuildError::ManifestError(format!(
+                "'{}' is not a sequential build of task {}",
+                path.display(),
+                task_id
+            )));
+        }
+        outputs.extend(build.outputs);
+        task.get_or_insert(build.task);
+    }
+    let Some(task) = task else {
+        return Err(BuildError::ManifestError(format!(
+            "No build manifests of task {} found in '{}'",
+            task_id,
+            output_directory.display()
+        )));
+    };
+    // UUIDs are time-ordered, so the variants are in the order they were created
+    outputs.sort_by_key(|instance| instance.uuid);
+    Ok(TaskBuildContainer {
+        schema_version: BUILD_MANIFEST_SCHEMA_VERSION,
+        basedir: output_directory.to_path_buf(),
+        task,
+        outputs,
+        batched: false,
+    })
+}
+
+/// Cache key of a single instance build, if caching is enabled.
+/// Directories in `excluded` are outputs inside the task directory and not part of the inputs.
+fn instance_cache_key(
+    options: &BuildOptions,
+    build_manifest: &TaskBuildContainer,
+    task_directory: &Path,
+    excluded: &[PathBuf],
+) -> Option<CacheKey> {
+    options.cache.as_ref()?;
+    let instance = &build_manifest.outputs[0];
+    match CacheKey::new(
+        &build_manifest.task,
+        task_directory,
+        excluded,
+        instance.uuid,
+        &instance.stage_flags,
+    ) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            tracing::warn!("Build cache is not used for this build: {}", e);
+            None
+        }
+    }
+}
+
+/// Copy the outputs of an identical earlier build, returns false if the builder must be run
+fn restore_from_cache(
+    cache: &BuildCache,
+    key: &CacheKey,
+    build_manifest: &mut TaskBuildContainer,
+    task_directory: &Path,
+) -> Result<bool, BuildError> {
+    let instance = &build_manifest.outputs[0];
+    match cache.restore(key, &instance.task_instance_dir) {
+        Ok(true) => {
+            tracing::info!(
+                "Reusing the cached build of task {} for UUID {}",
+                build_manifest.task.id,
+                instance.uuid
+            );
+            if let Some(path) = &instance.build_log
+                && let Err(e) = fs::write(
+                    path,
+                    "Restored from the build cache, the builder was not run\n",
+                )
+            {
+                tracing::warn!("Cannot write the build log '{}': {}", path.display(), e);
+            }
+            collect_outputs(build_manifest, task_directory)?;
+            Ok(true)
+        }
+        Ok(false) => Ok(false),
+        Err(e) => {
+            tracing::warn!("Failed to read the build cache, building again: {}", e);
+            Ok(false)
+        }
+    }
+}
+
+fn store_to_cache(cache: &BuildCache, key: &CacheKey, build_manifest: &TaskBuildContainer) {
+    let instance = &build_manifest.outputs[0];
+    if let Err(e) = cache.store(
+        key,
+        &instance.task_instance_dir,
+        &build_manifest.task.id,
+        instance.uuid,
+    ) {
+        tracing::warn!("Failed to store the build into the cache: {}", e);
+    }
+}
+
 fn run_subprocess(
     program: &str,
     args: Vec<&str>,
     build_manifest: &mut TaskBuildContainer,
     build_envs: HashMap<String, String>,
+    options: &BuildOptions,
 ) -> Result<(), BuildError> {
     tracing::debug!("Running subprocess: {} with args: {:?}", program, args);
 
-    let output = std::process::Command::new(program)
-        .args(args)
-        .envs(build_envs) // Use merged environment instead of env_clear()
-        .current_dir(&build_manifest.task.build.directory)
-        .output();
-
-    let output = match output {
-        Ok(output) => output,
-        Err(e) => {
-            return Err(BuildError::ShellSubprocessError(format!(
-                "The build process of task {} failed prematurely: {}",
-                build_manifest.task.id, e
-            )));
+    let command = builder_command(
+        program,
+        &args,
+        &build_manifest.task.build.directory,
+        build_manifest,
+        build_envs.clone(),
+    )?;
+    let task_id = &build_manifest.task.id;
+    let uuid = (!build_manifest.batched).then(|| build_manifest.outputs[0].uuid);
+    let on_line = |line: &str| {
+        tracing::info!("{}", line);
+        if options.events.is_enabled() {
+            options.events.emit(BuildEvent::BuilderOutput {
+                task_id: task_id.clone(),
+                uuid,
+                line: line.to_string(),
+            });
         }
     };
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        for line in stdout.lines() {
-            tracing::info!("{}", line);
-        }
-        build_manifest.validate_output()?;
 
-        // If the task has a seed-based flag, we must capture the resulting flag from the process output
-        // Stored into the file flags.json by default, using same key as the passed environment variable
-        map_rng_seed_to_flag(
-            &mut build_manifest.outputs,
-            &build_manifest.basedir,
-            build_manifest.task,
-        )?;
+    let started = Instant::now();
+    let output = run_builder(command, options.cancel.as_deref(), None, &on_line);
+    write_build_log(
+        build_manifest,
+        &[program].iter().chain(&args).copied().collect::<Vec<_>>(),
+        &build_envs,
+        started.elapsed(),
+        &output,
+    );
+    let task_directory = build_manifest.task.build.directory.clone();
+    builder_result(build_manifest, &task_directory, output)
+}
 
-        Ok(())
-    } else {
-        Err(BuildError::ShellSubprocessError(format!(
-            "The build process for task {} failed with non-zero exit code. Error: {}",
-            build_manifest.task.id,
-            std::str::from_utf8(&output.stderr).unwrap_or("Unable to read stderr")
-        )))
+/// Like [`std::process::Command::output`], but passes the lines of stdout to `on_line` as they are printed
+///
+/// With a cancel flag or a deadline, the builder runs in its own process group, so that the processes it starts are
+/// terminated with it. Returns `None` if the flag is set or the deadline passes before the builder exits.
+pub(crate) fn run_builder(
+    mut command: std::process::Command,
+    cancel: Option<&AtomicBool>,
+    deadline: Option<Instant>,
+    on_line: &(dyn Fn(&str) + Sync),
+) -> std::io::Result<Option<Output>> {
+    let watched = cancel.is_some() || deadline.is_some();
+    if watched {
+        command.process_group(0);
+    }
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = command.spawn()?;
+    let stdout_pipe = child.stdout.take();
+    let stderr_pipe = child.stderr.take();
+    std::thread::scope(|scope| {
+        let stdout = scope.spawn(|| {
+            let mut data = Vec::new();
+            let Some(pipe) = stdout_pipe else {
+                return data;
+            };
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+                on_line(String::from_utf8_lossy(&line).trim_end_matches(['\n', '\r']));
+                data.append(&mut line);
+            }
+            data
+        });
+        let stderr = scope.spawn(|| {
+            let mut data = Vec::new();
+            if let Some(mut pipe) = stderr_pipe {
+                let _ = pipe.read_to_end(&mut data);
+            }
+            data
+        });
+        let status = if watched {
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if cancel.is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+                    || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    terminate(&mut child)?;
+                    return Ok(None);
+                }
+                std::thread::sleep(CANCEL_POLL_INTERVAL);
+            }
+        } else {
+            child.wait()?
+        };
+        Ok(Some(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        }))
+    })
+}
+
+/// Send SIGTERM to the process group of the child, and SIGKILL if it is still running after the grace period
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    let group = -(child.id() as libc::pid_t);
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(group, libc::SIGTERM) };
+    let deadline = Instant::now() + CANCEL_GRACE_PERIOD;
+    while Instant::now() < deadline && child.try_wait()?.is_none() {
+        std::thread::sleep(CANCEL_POLL_INTERVAL);
     }
+    // Other processes of the group may outlive the builder itself
+    // SAFETY: as above
+    unsafe { libc::kill(group, libc::SIGKILL) };
+    child.wait().map(|_| ())
 }
 
 async fn run_subprocess_async(
@@ -295,6 +890,7 @@ async fn run_subprocess_async(
     task_directory: &Path,
     build_manifest: &mut TaskBuildContainer<'_>,
     build_envs: HashMap<String, String>,
+    options: &BuildOptions,
 ) -> Result<(), BuildError> {
     tracing::debug!(
         "Running subprocess: {} with args: {:?} in folder {}",
@@ -302,35 +898,109 @@ async fn run_subprocess_async(
         args,
         &task_directory.display()
     );
-    let output = TokioCommand::new(program)
-        .args(args)
-        .envs(build_envs) // Use merged environment
-        .current_dir(task_directory)
-        .output()
-        .await
-        .map_err(|e| {
-            BuildError::ShellSubprocessError(format!(
-                "The build process of task {} failed prematurely: {}",
-                build_manifest.task.id, e
-            ))
-        })?;
-
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        for line in stdout.lines() {
+    let command = builder_command(
+        program,
+        &args,
+        task_directory,
+        build_manifest,
+        build_envs.clone(),
+    )?;
+    let events = options.events.clone();
+    let cancel = options.cancel.clone();
+    let task_id = build_manifest.task.id.clone();
+    let uuid = build_manifest.outputs[0].uuid;
+    // The pipes are read on a blocking thread, so that the lines can be reported as they arrive
+    let started = Instant::now();
+    let output = tokio::task::spawn_blocking(move || {
+        let on_line = |line: &str| {
             tracing::info!("{}", line);
-        }
-        build_manifest.validate_output()?;
+            events.emit(BuildEvent::BuilderOutput {
+                task_id: task_id.clone(),
+                uuid: Some(uuid),
+                line: line.to_string(),
+            });
+        };
+        run_builder(command, cancel.as_deref(), None, &on_line)
+    })
+    .await
+    .map_err(|e| BuildError::ThreadError(e.to_string()))?;
+    write_build_log(
+        build_manifest,
+        &[program].iter().chain(&args).copied().collect::<Vec<_>>(),
+        &build_envs,
+        started.elapsed(),
+        &output,
+    );
+    builder_result(build_manifest, task_directory, output)
+}
 
-        // If the task has a seed-based flag, we must capture the resulting flag from the process output
-        // Stored into the file flags.json by default, using same key as the passed environment variable
-        map_rng_seed_to_flag(
-            &mut build_manifest.outputs,
-            &build_manifest.basedir,
-            build_manifest.task,
-        )?;
+/// Write the command, exit status, duration, environment and output of the builder into the build log
+///
+/// The flags in the environment are redacted. Failing to write the log does not fail the build.
+fn write_build_log(
+    build_manifest: &TaskBuildContainer,
+    command: &[&str],
+    build_envs: &HashMap<String, String>,
+    duration: Duration,
+    output: &std::io::Result<Option<Output>>,
+) {
+    let Some(path) = build_manifest
+        .outputs
+        .first()
+        .and_then(|instance| instance.build_log.as_ref())
+    else {
+        return;
+    };
+    let status = match output {
+        Ok(Some(output)) => output.status.to_string(),
+        Ok(None) => "cancelled".to_string(),
+        Err(e) => format!("failed to start: {e}"),
+    };
+    let mut log = format!(
+        "command: {}\nstatus: {}\nduration: {:.3} s\nenvironment:\n",
+        command.join(" "),
+        status,
+        duration.as_secs_f64()
+    );
+    let mut envs: Vec<_> = build_envs.iter().collect();
+    envs.sort();
+    for (name, value) in envs {
+        let value = if name.starts_with("FLAG_") {
+            "<redacted>"
+        } else {
+            value
+        };
+        log.push_str(&format!("  {name}={value}\n"));
+    }
+    if let Ok(Some(output)) = output {
+        log.push_str("--- stdout ---\n");
+        log.push_str(&String::from_utf8_lossy(&output.stdout));
+        log.push_str("--- stderr ---\n");
+        log.push_str(&String::from_utf8_lossy(&output.stderr));
+    }
+    if let Err(e) = fs::write(path, log) {
+        tracing::warn!("Cannot write the build log '{}': {}", path.display(), e);
+    }
+}
 
-        Ok(())
+/// Collect the outputs of a successful builder, or turn the failure into an error
+fn builder_result(
+    build_manifest: &mut TaskBuildContainer,
+    task_directory: &Path,
+    output: std::io::Result<Option<Output>>,
+) -> Result<(), BuildError> {
+    let output = match output {
+        Ok(Some(output)) => output,
+        Ok(None) => {
+            return Err(BuildError::Cancelled(format!(
+                "the builder of task {} was terminated",
+                build_manifest.task.id
+            )));
+        }
+        Err(e) => return Err(spawn_error(build_manifest, e)),
+    };
+    if output.status.success() {
+        collect_outputs(build_manifest, task_directory)
     } else {
         Err(BuildError::ShellSubprocessError(format!(
             "The build process for task {} failed with non-zero exit code. Error: {}",
@@ -340,11 +1010,16 @@ async fn run_subprocess_async(
     }
 }
 
+/// Build all the variants of the task with a single builder run
+///
+/// The events of the builder output have no UUID, since the variants are built together. The build cache is not used,
+/// because every batch has new UUIDs.
 pub fn build_batch<'a>(
     module_config: &'a ModuleConfiguration,
     task_config: &'a Task,
     output_directory: &'a Path,
     validate: bool,
+    options: &BuildOptions,
 ) -> Result<TaskBuildContainer<'a>, BuildError> {
     if !task_config.build.directory.exists() {
         return Err(BuildError::InvalidOutputDirectory(
@@ -372,7 +1047,7 @@ pub fn build_batch<'a>(
     //
     let mut entries = Vec::with_capacity(uuids.len());
     for uuid_value in uuids {
-        let flags = create_flags_by_task(task_config, module_config, uuid_value);
+        let flags = create_flags_by_task(task_config, module_config, uuid_value)?;
 
         let expected_outputs: Vec<OutputItem> = task_config
             .build
@@ -385,19 +1060,20 @@ pub fn build_batch<'a>(
         let task_instance_dir =
             verify_output_dir(output_directory, &uuid_value.to_string(), &task_config.id)?;
 
-        let entry = IntermediateOutput {
-            uuid: uuid_value,
-            stage_flags: flags,
-            task_instance_dir,
-            outputs: expected_outputs,
-        };
+        let mut entry =
+            IntermediateOutput::new(uuid_value, flags, task_instance_dir, expected_outputs);
+        if !validate {
+            // Variants are built by a single builder run, so they share the log
+            entry.build_log = Some(output_directory.join(DEFAULT_BUILD_LOG));
+        }
 
         entries.push(entry);
     }
     // PANICS: We are creating the file in the output directory, which is guaranteed to exist (unless someone removed it between check and this point)
     let mut build_manifest = TaskBuildContainer {
+        schema_version: BUILD_MANIFEST_SCHEMA_VERSION,
         basedir: builder_output_dir,
-        task: task_config,
+        task: Cow::Borrowed(task_config),
         outputs: entries,
         batched: true,
     };
@@ -409,12 +1085,13 @@ pub fn build_batch<'a>(
         return Ok(build_manifest);
     }
 
-    let mut build_envs = HashMap::from([(
-        "BUILD_MANIFEST".to_string(),
-        json_path.to_str().unwrap_or_default().to_string(),
-    )]);
+    // Variants have their own flags, which are only available from the manifest
+    let mut build_envs =
+        build_environment(&json_path, &task_config.id, &build_manifest.basedir, None);
     let (program, program_args) = match task_config.build.builder {
         Builder::Shell(ref entrypoint) => ("sh", vec![entrypoint.entrypoint.as_str()]),
+        // The entrypoint is run with the shell of the image
+        Builder::Container(ref container) => ("sh", vec![container.entrypoint.as_str()]),
         Builder::Nix(ref entrypoint) => {
             // For nix to work, we need to set the environment variables
             let mut preserved_env = HashMap::new();
@@ -440,9 +1117,38 @@ pub fn build_batch<'a>(
         }
     };
 
-    run_subprocess(program, program_args, &mut build_manifest, build_envs)?;
-
-    Ok(build_manifest)
+    let uuids: Vec<Uuid> = build_manifest
+        .outputs
+        .iter()
+        .map(|output| output.uuid)
+        .collect();
+    for uuid in &uuids {
+        options.events.emit(BuildEvent::VariantStarted {
+            task_id: task_config.id.clone(),
+            uuid: *uuid,
+        });
+    }
+    let result = run_subprocess(
+        program,
+        program_args,
+        &mut build_manifest,
+        build_envs,
+        options,
+    )
+    .and_then(|()| build_manifest.write_manifest(&json_path));
+    match &result {
+        Ok(()) => {
+            for uuid in uuids {
+                emit_finished(&options.events, task_config, uuid, false);
+            }
+        }
+        Err(error) => options.events.emit(BuildEvent::Failed {
+            task_id: task_config.id.clone(),
+            uuid: None,
+            error: error.to_string(),
+        }),
+    }
+    result.map(|()| build_manifest)
 }
 
 fn map_rng_seed_to_flag(
@@ -500,10 +1206,71 @@ pub fn build_sequential<'a>(
     uuid: Uuid,
     output_directory: &Path,
     // If the build is repeated, tells the number, starting from 1
+    build_number: usize,
+    validate: bool,
+    options: &BuildOptions,
+) -> Result<IntermediateOutput, BuildError> {
+    if validate {
+        return sequential_variant(
+            module_config,
+            task_config,
+            uuid,
+            output_directory,
+            build_number,
+            validate,
+            options,
+        );
+    }
+    options.events.emit(BuildEvent::VariantStarted {
+        task_id: task_config.id.clone(),
+        uuid,
+    });
+    let result = sequential_variant(
+        module_config,
+        task_config,
+        uuid,
+        output_directory,
+        build_number,
+        validate,
+        options,
+    );
+    if let Err(error) = &result {
+        emit_failure(&options.events, task_config, uuid, error);
+    }
+    result
+}
+
+fn emit_failure(events: &EventSink, task: &Task, uuid: Uuid, error: &BuildError) {
+    events.emit(BuildEvent::Failed {
+        task_id: task.id.clone(),
+        uuid: Some(uuid),
+        error: error.to_string(),
+    });
+}
+
+fn emit_finished(events: &EventSink, task: &Task, uuid: Uuid, cached: bool) {
+    let task_id = task.id.clone();
+    events.emit(BuildEvent::OutputsValidated {
+        task_id: task_id.clone(),
+        uuid,
+    });
+    events.emit(BuildEvent::VariantFinished {
+        task_id,
+        uuid,
+        cached,
+    });
+}
+
+fn sequential_variant<'a>(
+    module_config: &'a ModuleConfiguration,
+    task_config: &'a Task,
+    uuid: Uuid,
+    output_directory: &Path,
     _build_number: usize,
     validate: bool,
+    options: &BuildOptions,
 ) -> Result<IntermediateOutput, BuildError> {
-    let flags = create_flags_by_task(task_config, module_config, uuid);
+    let flags = create_flags_by_task(task_config, module_config, uuid)?;
     // Create the base output directory
     if !output_directory.exists() {
         fs::create_dir_all(output_directory).map_err(|e| {
@@ -523,18 +1290,21 @@ pub fn build_sequential<'a>(
         .iter()
         .map(|output| OutputItem::new(output.kind.clone()))
         .collect();
-    let intermediate = IntermediateOutput::new(uuid, flags, task_instance_dir, expected_outputs);
+    let mut intermediate =
+        IntermediateOutput::new(uuid, flags, task_instance_dir, expected_outputs);
 
     let json_path = if validate {
         // No race condition if we are validating
         output_directory.join(DEFAULT_BUILD_MANIFEST)
     } else {
         // For sequential builds we must use the task instance directory to avoid race condition
+        intermediate.build_log = Some(intermediate.task_instance_dir.join(DEFAULT_BUILD_LOG));
         intermediate.task_instance_dir.join(DEFAULT_BUILD_MANIFEST)
     };
     let mut build_manifest = TaskBuildContainer {
+        schema_version: BUILD_MANIFEST_SCHEMA_VERSION,
         basedir: output_directory.to_path_buf(),
-        task: task_config,
+        task: Cow::Borrowed(task_config),
         outputs: vec![intermediate],
         batched: false,
     };
@@ -546,32 +1316,61 @@ pub fn build_sequential<'a>(
         return Ok(build_manifest.outputs[0].clone());
     }
 
-    let build_envs = HashMap::from([(
-        "BUILD_MANIFEST".to_string(),
-        json_path.to_str().unwrap_or_default().to_string(),
-    )]);
+    let cache_key = instance_cache_key(
+        options,
+        &build_manifest,
+        &task_config.build.directory,
+        &[output_directory.to_path_buf()],
+    );
+    if let (Some(cache), Some(key)) = (&options.cache, &cache_key)
+        && restore_from_cache(
+            cache,
+            key,
+            &mut build_manifest,
+            &task_config.build.directory,
+        )?
+    {
+        // The manifest is written again to record that the variant is built
+        build_manifest.write_manifest(&json_path)?;
+        emit_finished(&options.events, task_config, uuid, true);
+        return Ok(build_manifest.outputs.remove(0));
+    }
+
+    let build_envs = build_environment(
+        &json_path,
+        &task_config.id,
+        &build_manifest.outputs[0].task_instance_dir,
+        Some(&build_manifest.outputs[0]),
+    );
 
     match task_config.build.builder {
-        Builder::Shell(ref entrypoint) => {
+        Builder::Shell(Shell { ref entrypoint })
+        | Builder::Container(Container { ref entrypoint, .. }) => {
             tracing::debug!(
                 "Running shell command: {} in directory: {}",
-                entrypoint.entrypoint,
+                entrypoint,
                 &task_config.build.directory.display()
             );
 
             run_subprocess(
                 "sh",
-                vec![&entrypoint.entrypoint],
+                vec![entrypoint],
                 &mut build_manifest,
                 build_envs,
+                options,
             )?
         }
         Builder::Nix(_) => todo!("Nix builder not implemented"),
     }
+    if let (Some(cache), Some(key)) = (&options.cache, &cache_key) {
+        store_to_cache(cache, key, &build_manifest);
+    }
+    build_manifest.write_manifest(&json_path)?;
     debug_assert!(
         build_manifest.outputs.len() == 1,
         "The sequential build should have only one output"
     );
+    emit_finished(&options.events, task_config, uuid, false);
     Ok(build_manifest.outputs.remove(0))
 }
 
@@ -581,6 +1380,28 @@ pub async fn build_task<'a>(
     task_directory: &Path,
     task_id: &str,
     uuid: Uuid,
+    options: &BuildOptions,
+) -> Result<TaskBuildContainer<'a>, BuildError> {
+    let Some(task) = module_config.get_task_by_id(task_id) else {
+        return Err(BuildError::TaskNotFound(task_id.to_string()));
+    };
+    options.events.emit(BuildEvent::VariantStarted {
+        task_id: task.id.clone(),
+        uuid,
+    });
+    let result = server_variant(module_config, task_directory, task_id, uuid, options).await;
+    if let Err(error) = &result {
+        emit_failure(&options.events, task, uuid, error);
+    }
+    result
+}
+
+async fn server_variant<'a>(
+    module_config: &'a ModuleConfiguration,
+    task_directory: &Path,
+    task_id: &str,
+    uuid: Uuid,
+    options: &BuildOptions,
 ) -> Result<TaskBuildContainer<'a>, BuildError> {
     tracing::debug!(
         "Building task {} with UUID {} in directory {}",
@@ -600,7 +1421,7 @@ pub async fn build_task<'a>(
             .await
             .map_err(|e| BuildError::InvalidOutputDirectory(e.to_string()))?;
 
-        let flags = create_flags_by_task(task, module_config, uuid);
+        let flags = create_flags_by_task(task, module_config, uuid)?;
 
         let expected_outputs: Vec<OutputItem> = task
             .build
@@ -609,8 +1430,9 @@ pub async fn build_task<'a>(
             .map(|output| OutputItem::new(output.kind.clone()))
             .collect();
 
-        let intermediate_output =
+        let mut intermediate_output =
             IntermediateOutput::new(uuid, flags, student_output_dir.clone(), expected_outputs);
+        intermediate_output.build_log = Some(student_output_dir.join(DEFAULT_BUILD_LOG));
 
         let mut build_container = TaskBuildContainer::new(
             task_directory.to_path_buf(),
@@ -619,45 +1441,240 @@ pub async fn build_task<'a>(
             false,
         );
 
-        let manifest_path = student_output_dir.join("build-manifest.json");
+        let manifest_path = student_output_dir.join(DEFAULT_BUILD_MANIFEST);
+
+        write_manifest_async(&manifest_path, &build_container).await?;
 
-        tokio::fs::write(
+        let build_envs = build_environment(
             &manifest_path,
-            serde_json::to_string_pretty(&build_container)?,
-        )
-        .await
-        .map_err(|e| BuildError::SerdeDerserializationFailed(e.to_string()))?;
+            &task.id,
+            &student_output_dir,
+            Some(&build_container.outputs[0]),
+        );
 
-        let build_envs = HashMap::from([(
-            "BUILD_MANIFEST".to_string(),
-            manifest_path.to_str().unwrap_or_default().to_string(),
-        )]);
+        // Outputs of all the students are inside the task directory
+        let cache_key = instance_cache_key(
+            options,
+            &build_container,
+            task_directory,
+            &[task_directory.join("output")],
+        );
+        if let (Some(cache), Some(key)) = (&options.cache, &cache_key)
+            && restore_from_cache(cache, key, &mut build_container, task_directory)?
+        {
+            write_manifest_async(&manifest_path, &build_container).await?;
+            emit_finished(&options.events, task, uuid, true);
+            return Ok(build_container);
+        }
 
         match task.build.builder {
-            Builder::Shell(ref entrypoint) => {
+            Builder::Shell(Shell { ref entrypoint })
+            | Builder::Container(Container { ref entrypoint, .. }) => {
                 tracing::debug!(
                     "Running shell command: {} in directory: {}",
-                    entrypoint.entrypoint,
+                    entrypoint,
                     task_directory.display()
                 );
 
                 run_subprocess_async(
                     "sh",
-                    vec![&entrypoint.entrypoint],
+                    vec![entrypoint],
                     task_directory,
                     &mut build_container,
                     build_envs,
+                    options,
                 )
                 .await?;
             }
             Builder::Nix(_) => todo!("Nix builder not implemented"),
         }
 
-        // Validate the output files
-        build_container.validate_output()?;
+        if let (Some(cache), Some(key)) = (&options.cache, &cache_key) {
+            store_to_cache(cache, key, &build_container);
+        }
+        write_manifest_async(&manifest_path, &build_container).await?;
+        emit_finished(&options.events, task, uuid, false);
 
         Ok(build_container)
     } else {
         Err(BuildError::TaskNotFound(task_id.to_string()))
     }
 }
+
+async fn write_manifest_async(
+    path: &Path,
+    build_container: &TaskBuildContainer<'_>,
+) -> Result<(), BuildError> {
+    tokio::fs::write(path, serde_json::to_string_pretty(build_container)?)
+        .await
+        .map_err(|e| BuildError::SerdeDerserializationFailed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flag_generator::Algorithm;
+
+    #[test]
+    fn test_build_environment() {
+        let uuid = Uuid::now_v7();
+        let flags = vec![
+            Flag::new_random_flag("stage1".to_string(), 16),
+            Flag::new_user_flag(
+                "stage2".to_string(),
+                &Algorithm::HMAC_SHA3_256,
+                "secret",
+                "stage2",
+                &uuid,
+            ),
+        ];
+        let instance = IntermediateOutput::new(uuid, flags.clone(), "/out/task".into(), vec![]);
+        let envs = build_environment(
+            Path::new("/out/task/build-manifest.json"),
+            "task",
+            &instance.task_instance_dir,
+            Some(&instance),
+        );
+        assert_eq!(envs["BUILD_MANIFEST"], "/out/task/build-manifest.json");
+        assert_eq!(envs["TASK_ID"], "task");
+        assert_eq!(envs["TASK_UUID"], uuid.to_string());
+        assert_eq!(envs["OUTPUT_DIR"], "/out/task");
+        assert_eq!(envs["FLAG_PURE_RANDOM_STAGE1"], flags[0].flag_string());
+        assert_eq!(envs["FLAG_USER_DERIVED_STAGE2"], flags[1].flag_string());
+
+        // Batch builds only get the common values
+        let envs = build_environment(
+            Path::new("/out/build-manifest.json"),
+            "task",
+            Path::new("/out"),
+            None,
+        );
+        assert_eq!(envs.len(), 3);
+        assert!(!envs.contains_key("TASK_UUID"));
+    }
+
+    #[test]
+    fn test_manifest_schema() {
+        let schema: serde_json::Value = serde_json::from_str(BUILD_MANIFEST_SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            BUILD_MANIFEST_SCHEMA_VERSION
+        );
+        let task: Task = toml::from_str(
+            r#"
+            id = "task001"
+            name = "Task"
+            points = 1.0
+            stages = [{ flag = { kind = "pure_random" } }]
+            [build]
+            directory = "."
+            builder = { shell = { entrypoint = "build.sh" } }
+            output = [{ kind = { readme = "readme.txt" } }]
+            enabled_modes = ["sequential"]
+            "#,
+        )
+        .unwrap();
+        let instance = IntermediateOutput::new(
+            Uuid::now_v7(),
+            vec![Flag::new_random_flag("task001".to_string(), 16)],
+            "/out/task001".into(),
+            vec![OutputItem::new(OutputKind::Readme("readme.txt".into()))],
+        );
+        let container = TaskBuildContainer::new("/out".into(), &task, vec![instance], false);
+        let manifest = serde_json::to_value(&container).unwrap();
+        // Every field required by the schema is present in the manifest
+        for field in schema["required"].as_array().unwrap() {
+            assert!(manifest.get(field.as_str().unwrap()).is_some());
+        }
+        for field in schema["$defs"]["instance"]["required"].as_array().unwrap() {
+            assert!(
+                manifest["outputs"][0]
+                    .get(field.as_str().unwrap())
+                    .is_some()
+            );
+        }
+        let flag = manifest["outputs"][0]["stage_flags"][0]
+            .as_object()
+            .unwrap();
+        let flag_kinds = schema["$defs"]["flag"]["properties"].as_object().unwrap();
+        assert!(flag.keys().all(|kind| flag_kinds.contains_key(kind)));
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let config =
+            crate::config::read_check_toml("tests/data/configs/simple_shell.toml".as_ref())
+                .unwrap();
+        let task = config.get_task_by_id("task001").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let uuid = Uuid::now_v7();
+        let flags = create_flags_by_task(task, &config, uuid).unwrap();
+        let mut instance = IntermediateOutput::new(uuid, flags, dir.path().join("variant"), vec![]);
+        instance.status = VariantStatus::Built;
+        let container = TaskBuildContainer::new(dir.path().into(), task, vec![instance], false);
+        let path = dir.path().join(DEFAULT_BUILD_MANIFEST);
+        container.write_manifest(&path).unwrap();
+
+        let loaded = TaskBuildContainer::from_manifest(&path).unwrap();
+        assert_eq!(loaded.task.id, task.id);
+        assert_eq!(loaded.outputs[0].status, VariantStatus::Built);
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&container).unwrap()
+        );
+
+        let mut manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        manifest["schema_version"] = (BUILD_MANIFEST_SCHEMA_VERSION + 1).into();
+        fs::write(&path, manifest.to_string()).unwrap();
+        assert!(matches!(
+            TaskBuildContainer::from_manifest(&path),
+            Err(BuildError::ManifestError(_))
+        ));
+    }
+
+    #[test]
+    fn test_run_builder() {
+        let lines = std::sync::Mutex::new(Vec::new());
+        let on_line = |line: &str| lines.lock().unwrap().push(line.to_string());
+        let cancel = AtomicBool::new(false);
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "echo built; echo warning >&2; printf done"]);
+        let output = run_builder(command, Some(&cancel), None, &on_line)
+            .unwrap()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"built\ndone");
+        assert_eq!(output.stderr, b"warning\n");
+        assert_eq!(*lines.lock().unwrap(), ["built", "done"]);
+
+        // The sleep is a separate process which must be terminated with the shell
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "sleep 30; echo done"]);
+        let started = Instant::now();
+        let flag = Arc::clone(&cancel);
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            flag.store(true, Ordering::SeqCst);
+        });
+        assert!(
+            run_builder(command, Some(&cancel), None, &|_| {})
+                .unwrap()
+                .is_none()
+        );
+        canceller.join().unwrap();
+        assert!(started.elapsed() < CANCEL_GRACE_PERIOD);
+
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "sleep 30; echo done"]);
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(200);
+        assert!(
+            run_builder(command, None, Some(deadline), &|_| {})
+                .unwrap()
+                .is_none()
+        );
+        assert!(started.elapsed() < CANCEL_GRACE_PERIOD);
+    }
+}
@@ -0,0 +1,347 @@
+//! Content-addressed cache for build outputs
+//!
+//! A build is identified by the contents of the task directory, the build configuration, the UUID, the flags
+//! and the version of aínigma. When all of them match, the outputs of the earlier build are copied instead of running the builder.
+//! Each entry is a directory named by the key, containing the output files and an `entry.json` file.
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+use crate::config::{DEFAULT_BUILD_LOG, DEFAULT_BUILD_MANIFEST, Task};
+use crate::errors::BuildError;
+use crate::flag_generator::Flag;
+
+const ENTRY_FILENAME: &str = "entry.json";
+const FILES_DIRECTORY: &str = "files";
+
+/// Identifies the inputs of a single task instance build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    /// Hash the inputs of the build. Paths in `excluded` are skipped when hashing the task directory, e.g. output directories inside it.
+    pub fn new(
+        task: &Task,
+        task_directory: &Path,
+        excluded: &[PathBuf],
+        uuid: Uuid,
+        flags: &[Flag],
+    ) -> Result<Self, BuildError> {
+        let mut hasher = Sha256::new();
+        update_field(&mut hasher, env!("CARGO_PKG_VERSION").as_bytes());
+        update_field(&mut hasher, task.id.as_bytes());
+        let build_config =
+            serde_json::to_string(&(&task.build.builder, &task.build.output, &task.build.sandbox))?;
+        update_field(&mut hasher, build_config.as_bytes());
+        update_field(&mut hasher, uuid.as_bytes());
+        for flag in flags {
+            let (key, value) = flag.get_flag_type_value_pair();
+            update_field(&mut hasher, key.as_bytes());
+            update_field(&mut hasher, value.as_bytes());
+        }
+        let excluded = excluded
+            .iter()
+            .filter_map(|path| path.canonicalize().ok())
+            .collect::<Vec<_>>();
+        let task_directory = task_directory
+            .canonicalize()
+            .map_err(|e| cache_error(task_directory, e))?;
+        hash_directory(&mut hasher, &task_directory, &task_directory, &excluded)?;
+        Ok(CacheKey(format!("{:x}", hasher.finalize())))
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Length prefix keeps the boundaries of the fields unambiguous
+fn update_field(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+fn hash_directory(
+    hasher: &mut Sha256,
+    root: &Path,
+    directory: &Path,
+    excluded: &[PathBuf],
+) -> Result<(), BuildError> {
+    let mut entries = fs::read_dir(directory)
+        .map_err(|e| cache_error(directory, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| cache_error(directory, e))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if excluded.contains(&path) {
+            continue;
+        }
+        // Follows symbolic links, the task directory itself can be one
+        let metadata = fs::metadata(&path).map_err(|e| cache_error(&path, e))?;
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        update_field(hasher, relative.to_string_lossy().as_bytes());
+        if metadata.is_dir() {
+            hash_directory(hasher, root, &path, excluded)?;
+        } else {
+            let mut file = fs::File::open(&path).map_err(|e| cache_error(&path, e))?;
+            hasher.update(metadata.len().to_le_bytes());
+            std::io::copy(&mut file, hasher).map_err(|e| cache_error(&path, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct CacheEntry {
+    task_id: String,
+    uuid: Uuid,
+    ainigma_version: String,
+}
+
+/// Build cache in a local directory
+#[derive(Debug, Clone)]
+pub struct BuildCache {
+    root: PathBuf,
+}
+
+impl BuildCache {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+    /// `AINIGMA_CACHE_DIR`, or `ainigma` in the user cache directory (`XDG_CACHE_HOME` or `~/.cache`)
+    pub fn default_location() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("AINIGMA_CACHE_DIR") {
+            return Some(PathBuf::from(dir));
+        }
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|dir| dir.join("ainigma"))
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Copy the cached outputs into the instance directory. Returns false if there is no entry for the key.
+    pub fn restore(&self, key: &CacheKey, instance_dir: &Path) -> Result<bool, BuildError> {
+        let entry_dir = self.root.join(key.as_str());
+        if !entry_dir.join(ENTRY_FILENAME).is_file() {
+            return Ok(false);
+        }
+        copy_directory(&entry_dir.join(FILES_DIRECTORY), instance_dir)?;
+        // Pruning is based on the last use of the entry
+        fs::File::options()
+            .write(true)
+            .open(entry_dir.join(ENTRY_FILENAME))
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .map_err(|e| cache_error(&entry_dir, e))?;
+        Ok(true)
+    }
+
+    /// Store the outputs of a finished build from the instance directory, except the build manifest
+    pub fn store(
+        &self,
+        key: &CacheKey,
+        instance_dir: &Path,
+        task_id: &str,
+        uuid: Uuid,
+    ) -> Result<(), BuildError> {
+        let entry_dir = self.root.join(key.as_str());
+        if entry_dir.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.root).map_err(|e| cache_error(&self.root, e))?;
+        // Entry is prepared in a temporary directory so that a partial entry is never visible
+        let staging = tempfile::Builder::new()
+            .prefix(".staging-")
+            .tempdir_in(&self.root)
+            .map_err(|e| cache_error(&self.root, e))?;
+        copy_directory(instance_dir, &staging.path().join(FILES_DIRECTORY))?;
+        let entry = CacheEntry {
+            task_id: task_id.to_string(),
+            uuid,
+            ainigma_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let entry_file = staging.path().join(ENTRY_FILENAME);
+        fs::write(&entry_file, serde_json::to_string_pretty(&entry)?)
+            .map_err(|e| cache_error(&entry_file, e))?;
+        let staging = staging.keep();
+        if let Err(e) = fs::rename(&staging, &entry_dir) {
+            let _ = fs::remove_dir_all(&staging);
+            // Another build stored the same entry at the same time
+            if !entry_dir.exists() {
+                return Err(cache_error(&entry_dir, e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the entries which have not been used within `older_than`, or all entries if it is not set.
+    /// Returns the number of removed entries.
+    pub fn prune(&self, older_than: Option<Duration>) -> Result<usize, BuildError> {
+        if !self.root.exists() {
+            return Ok(0);
+        }
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.root).map_err(|e| cache_error(&self.root, e))? {
+            let path = entry.map_err(|e| cache_error(&self.root, e))?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            // Entries being stored do not have the entry file yet, so the directory itself tells their age
+            let last_used = fs::metadata(path.join(ENTRY_FILENAME))
+                .or_else(|_| fs::metadata(&path))
+                .and_then(|metadata| metadata.modified())
+                .map_err(|e| cache_error(&path, e))?;
+            let expired = older_than.is_none_or(|older_than| {
+                now.duration_since(last_used)
+                    .is_ok_and(|age| age > older_than)
+            });
+            if expired {
+                fs::remove_dir_all(&path).map_err(|e| cache_error(&path, e))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn copy_directory(source: &Path, destination: &Path) -> Result<(), BuildError> {
+    fs::create_dir_all(destination).map_err(|e| cache_error(destination, e))?;
+    for entry in fs::read_dir(source).map_err(|e| cache_error(source, e))? {
+        let entry = entry.map_err(|e| cache_error(source, e))?;
+        let path = entry.path();
+        if entry.file_name() == DEFAULT_BUILD_MANIFEST || entry.file_name() == DEFAULT_BUILD_LOG {
+            continue;
+        }
+        let target = destination.join(entry.file_name());
+        if path.is_dir() {
+            copy_directory(&path, &target)?;
+        } else {
+            fs::copy(&path, &target).map_err(|e| cache_error(&path, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn cache_error(path: &Path, error: std::io::Error) -> BuildError {
+    BuildError::CacheError(format!("{}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flag_generator::Algorithm;
+
+    fn task() -> Task {
+        toml::from_str(
+            r#"
+            id = "task001"
+            name = "Task"
+            points = 1.0
+            stages = [{ flag = { kind = "user_derived" } }]
+            [build]
+            directory = "."
+            builder = { shell = { entrypoint = "build.sh" } }
+            output = [{ kind = { resource = "binary" } }]
+            enabled_modes = ["sequential"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn flags(uuid: &Uuid) -> Vec<Flag> {
+        vec![Flag::new_user_flag(
+            "task001".to_string(),
+            &Algorithm::HMAC_SHA3_256,
+            "secret",
+            "task001",
+            uuid,
+        )]
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let task_dir = tempfile::tempdir().unwrap();
+        fs::write(task_dir.path().join("build.sh"), "echo build").unwrap();
+        fs::create_dir(task_dir.path().join("output")).unwrap();
+        let excluded = [task_dir.path().join("output")];
+        let uuid = Uuid::now_v7();
+        let task = task();
+
+        let key = CacheKey::new(&task, task_dir.path(), &excluded, uuid, &flags(&uuid)).unwrap();
+        let same = CacheKey::new(&task, task_dir.path(), &excluded, uuid, &flags(&uuid)).unwrap();
+        assert_eq!(key, same);
+
+        // Outputs of other builds do not change the key
+        fs::write(task_dir.path().join("output").join("binary"), "x").unwrap();
+        let with_output =
+            CacheKey::new(&task, task_dir.path(), &excluded, uuid, &flags(&uuid)).unwrap();
+        assert_eq!(key, with_output);
+
+        let other_uuid = Uuid::now_v7();
+        let other_user = CacheKey::new(
+            &task,
+            task_dir.path(),
+            &excluded,
+            other_uuid,
+            &flags(&other_uuid),
+        )
+        .unwrap();
+        assert_ne!(key, other_user);
+
+        fs::write(task_dir.path().join("build.sh"), "echo changed").unwrap();
+        let changed_source =
+            CacheKey::new(&task, task_dir.path(), &excluded, uuid, &flags(&uuid)).unwrap();
+        assert_ne!(key, changed_source);
+
+        let mut other_builder = task.clone();
+        other_builder.build.output.clear();
+        let changed_config = CacheKey::new(
+            &other_builder,
+            task_dir.path(),
+            &excluded,
+            uuid,
+            &flags(&uuid),
+        )
+        .unwrap();
+        assert_ne!(changed_source, changed_config);
+    }
+
+    #[test]
+    fn test_store_restore_prune() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = BuildCache::new(cache_dir.path().join("cache"));
+        let key = CacheKey("0123abcd".to_string());
+        let uuid = Uuid::now_v7();
+
+        let built = tempfile::tempdir().unwrap();
+        fs::write(built.path().join("binary"), "compiled").unwrap();
+        fs::write(built.path().join(DEFAULT_BUILD_MANIFEST), "{}").unwrap();
+        fs::create_dir(built.path().join("extra")).unwrap();
+        fs::write(built.path().join("extra").join("notes.txt"), "notes").unwrap();
+
+        let restored = tempfile::tempdir().unwrap();
+        assert!(!cache.restore(&key, restored.path()).unwrap());
+        cache.store(&key, built.path(), "task001", uuid).unwrap();
+        // Storing again is a no-op
+        cache.store(&key, built.path(), "task001", uuid).unwrap();
+        assert!(cache.restore(&key, restored.path()).unwrap());
+        assert_eq!(
+            fs::read_to_string(restored.path().join("binary")).unwrap(),
+            "compiled"
+        );
+        assert_eq!(
+            fs::read_to_string(restored.path().join("extra").join("notes.txt")).unwrap(),
+            "notes"
+        );
+        assert!(!restored.path().join(DEFAULT_BUILD_MANIFEST).exists());
+
+        assert_eq!(cache.prune(Some(Duration::from_secs(3600))).unwrap(), 0);
+        assert_eq!(cache.prune(None).unwrap(), 1);
+        assert!(!cache.restore(&key, restored.path()).unwrap());
+    }
+}
@@ -22,6 +22,10 @@ const DEFAULT_RANDOM_FLAG_LENGTH: u8 = 32;
 
 pub const DEFAULT_FLAGS_FILENAME: &str = "flags.json";
 pub const DEFAULT_BUILD_MANIFEST: &str = "build-manifest.json";
+/// Log of the builder run, written next to the build manifest
+pub const DEFAULT_BUILD_LOG: &str = "build.log";
+/// Version given to a secret defined with the single `secret` field
+pub const DEFAULT_SECRET_VERSION: u32 = 1;
 
 fn random_hex_secret() -> String {
     let mut random_bytes = vec![0u8; 32];
@@ -101,6 +105,28 @@ impl ModuleConfiguration {
         }
         None
     }
+    /// Whether any stage generates flags from the user derived secret
+    pub fn uses_derived_flags(&self) -> bool {
+        self.categories
+            .iter()
+            .flat_map(|category| &category.tasks)
+            .flat_map(|task| &task.stages)
+            .any(|stage| {
+                matches!(
+                    stage.flag.kind,
+                    FlagVariantKind::UserDerived | FlagVariantKind::RngSeed
+                )
+            })
+    }
+    /// Loads the flag secrets from their configured sources, see [`UserDerived::resolve_secrets`].
+    pub fn resolve_secrets(&mut self, base_dir: &Path, strict: bool) -> Result<(), ConfigError> {
+        if self.uses_derived_flags() {
+            self.flag_config
+                .user_derived
+                .resolve_secrets(base_dir, strict)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -130,6 +156,9 @@ pub struct Task {
     pub stages: Vec<TaskElement>,
     pub build: BuildConfig,
     pub batch: Option<BatchConfig>,
+    /// Metadata of the exam questions of the task
+    #[serde(default)]
+    pub moodle: MoodleConfig,
 }
 
 impl Task {
@@ -150,8 +179,24 @@ impl Task {
             stages,
             build,
             batch,
+            moodle: MoodleConfig::default(),
         }
     }
+    /// Points of each stage, the points of the task divided by the weights of the stages
+    pub fn stage_points(&self) -> Vec<f32> {
+        let total_weight: u32 = self
+            .stages
+            .iter()
+            .map(|stage| u32::from(stage.weight.unwrap_or(1)))
+            .sum();
+        self.stages
+            .iter()
+            .map(|stage| {
+                let weight = u32::from(stage.weight.unwrap_or(1));
+                self.points * weight as f32 / total_weight.max(1) as f32
+            })
+            .collect()
+    }
     /// Gets all task IDs for a task, including possible subtasks in `stages`
     /// Mainly used for validating that they are unique
     pub fn get_task_ids(&self) -> Vec<&str> {
@@ -185,6 +230,7 @@ pub struct FlagVariant {
 
 #[derive(Debug, Serialize, Clone, PartialEq, Eq)]
 #[non_exhaustive]
+#[serde(rename_all = "snake_case")]
 pub enum FlagVariantKind {
     UserDerived,
     PureRandom,
@@ -272,6 +318,7 @@ impl TaskElement {
 
 #[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash)]
 #[non_exhaustive]
+#[serde(rename_all = "lowercase")]
 pub enum BuildMode {
     Sequential,
     Batch,
@@ -353,6 +400,21 @@ pub struct BuildConfig {
     // Note: all modes are disabled by default - task must explicitly enable one!
     #[serde(deserialize_with = "deserialize_non_empty_build_modes")]
     pub enabled_modes: NonEmptyBuildModes,
+    /// Run the builder in a sandbox. Not sandboxed by default.
+    #[serde(default)]
+    pub sandbox: Option<SandboxConfig>,
+    /// Script which solves a built variant, used to verify that the variant is solvable
+    #[serde(default)]
+    pub solver: Option<Solver>,
+    /// What to do when a flag is found in the files given to the students
+    #[serde(default)]
+    pub leak_check: LeakCheck,
+    /// Bundle the resource files of each variant into a single archive
+    #[serde(default)]
+    pub package: Option<PackageConfig>,
+    /// Template of the instructions in the build directory, rendered for each variant, see [`crate::template`]
+    #[serde(default)]
+    pub readme_template: Option<PathBuf>,
 }
 impl AsRef<BuildConfig> for BuildConfig {
     fn as_ref(&self) -> &BuildConfig {
@@ -372,6 +434,11 @@ impl BuildConfig {
             builder,
             output,
             enabled_modes,
+            sandbox: None,
+            solver: None,
+            leak_check: LeakCheck::default(),
+            package: None,
+            readme_template: None,
         }
     }
     pub fn is_feature_enabled(&self, feature: BuildMode) -> bool {
@@ -382,6 +449,167 @@ impl BuildConfig {
     }
 }
 
+/// Metadata of the exam questions of a task, see [`crate::moodle`]
+///
+/// The texts are templates with the values of the variant, see [`crate::template::variables`]. Feedback and hints are
+/// HTML.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MoodleConfig {
+    /// Name of the question, the task name by default
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Feedback shown after the question is finished, whatever the answer was
+    #[serde(default)]
+    pub general_feedback: Option<String>,
+    /// Feedback of the answers which contain flags
+    #[serde(default = "MoodleConfig::default_correct_feedback")]
+    pub correct_feedback: String,
+    /// Feedback of any other answer
+    #[serde(default)]
+    pub incorrect_feedback: Option<String>,
+    /// Fraction of the points lost for each incorrect try, between 0 and 1. Moodle uses its default when unset.
+    #[serde(default)]
+    pub penalty: Option<f32>,
+    /// Hints shown after each incorrect try in the interactive mode
+    #[serde(default)]
+    pub hints: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Default for MoodleConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            general_feedback: None,
+            correct_feedback: Self::default_correct_feedback(),
+            incorrect_feedback: None,
+            penalty: None,
+            hints: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl MoodleConfig {
+    fn default_correct_feedback() -> String {
+        "Correct!".to_string()
+    }
+}
+
+/// Policy for flags found in the resource files or the readme, see [`crate::leak_scan`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LeakCheck {
+    Off,
+    #[default]
+    Warn,
+    Fail,
+}
+
+/// Archive of the resource files of a variant, see [`crate::package`]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PackageConfig {
+    #[serde(default)]
+    pub format: ArchiveFormat,
+    /// Name of the archive without the extension. `{task_id}`, `{uuid}` and `{short_uuid}` are replaced.
+    #[serde(default = "PackageConfig::default_name")]
+    pub name: String,
+}
+
+impl PackageConfig {
+    const PLACEHOLDERS: [&str; 3] = ["{task_id}", "{uuid}", "{short_uuid}"];
+
+    fn default_name() -> String {
+        "{task_id}-{short_uuid}".to_string()
+    }
+
+    /// File name of the archive of the variant
+    pub fn file_name(&self, task_id: &str, uuid: &Uuid) -> String {
+        let uuid = uuid.simple().to_string();
+        // The end of a v7 UUID is random, the beginning is a timestamp shared by the variants of a build
+        let short_uuid = &uuid[uuid.len() - 8..];
+        let name = self
+            .name
+            .replace("{task_id}", task_id)
+            .replace("{uuid}", &uuid)
+            .replace("{short_uuid}", short_uuid);
+        format!("{}.{}", name, self.format.extension())
+    }
+
+    /// The name must only use the known placeholders and it cannot be a path
+    fn check_name(&self) -> Result<(), String> {
+        let mut rest = self.name.clone();
+        for placeholder in Self::PLACEHOLDERS {
+            rest = rest.replace(placeholder, "");
+        }
+        if rest.contains(['{', '}']) {
+            return Err(format!(
+                "unknown placeholder in '{}', available are {}",
+                self.name,
+                Self::PLACEHOLDERS.join(", ")
+            ));
+        }
+        if self.name.trim().is_empty() || self.name.contains(['/', '\\']) {
+            return Err(format!("'{}' is not a valid file name", self.name));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+}
+
+/// Solver of the task, see [`crate::verify`]
+///
+/// The entrypoint is a shell script in the task directory.
+/// It is run in a directory with only the resource files of a variant, and must print the recovered flags.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Solver {
+    pub entrypoint: String,
+    /// Seconds the solver may run before it is terminated
+    #[serde(default = "Solver::default_timeout")]
+    pub timeout: u64,
+}
+
+impl Solver {
+    fn default_timeout() -> u64 {
+        60
+    }
+}
+
+/// Sandbox for the build process, see [`crate::sandbox`]
+///
+/// The builder can read the task directory and only write into the output directory.
+/// Limits are not applied if they are not set.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// Allow network access for the build
+    #[serde(default)]
+    pub network: bool,
+    /// Maximum CPU time in seconds
+    pub cpu_time: Option<u64>,
+    /// Maximum memory (address space) in megabytes
+    pub memory: Option<u64>,
+    /// Maximum number of processes of the user running the build, including the ones outside the sandbox
+    pub processes: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BuildOutputFile {
     pub kind: OutputKind,
@@ -446,21 +674,212 @@ impl Default for PureRandom {
         }
     }
 }
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// User-derived flags are HMACs over the user UUID, so the secret must survive restarts for the flags to be verifiable.
+/// Several versioned secrets can be configured: the active one is used for generating new flags and
+/// all of them are accepted when verifying.
+#[derive(Debug, Serialize, Clone, Default)]
 pub struct UserDerived {
-    #[serde(default = "flag_generator::Algorithm::default")]
     pub algorithm: flag_generator::Algorithm,
-    pub secret: String,
+    pub secrets: Vec<VersionedSecret>,
+    /// Version used for generating new flags. Defaults to the highest configured version.
+    pub active: Option<u32>,
 }
-impl Default for UserDerived {
-    fn default() -> Self {
-        UserDerived {
-            algorithm: flag_generator::Algorithm::default(),
-            secret: { random_hex_secret() },
+
+impl UserDerived {
+    pub fn new(
+        algorithm: flag_generator::Algorithm,
+        secrets: Vec<VersionedSecret>,
+        active: Option<u32>,
+    ) -> Result<UserDerived, ConfigError> {
+        let user_derived = UserDerived {
+            algorithm,
+            secrets,
+            active,
+        };
+        user_derived.check_versions()?;
+        Ok(user_derived)
+    }
+    fn check_versions(&self) -> Result<(), ConfigError> {
+        let mut versions = HashSet::new();
+        if !self.secrets.iter().all(|s| versions.insert(s.version)) {
+            return Err(ConfigError::SecretError(
+                "Secret versions must be unique".to_string(),
+            ));
+        }
+        if let Some(active) = self.active
+            && !versions.contains(&active)
+        {
+            return Err(ConfigError::SecretError(format!(
+                "Active secret version {active} is not defined"
+            )));
+        }
+        Ok(())
+    }
+    /// Loads the secret values from their sources. Relative secret files are resolved against `base_dir`.
+    /// When no secret is configured, a strict caller gets an error, while others get a random secret which only lives as long as the process.
+    pub fn resolve_secrets(&mut self, base_dir: &Path, strict: bool) -> Result<(), ConfigError> {
+        self.resolve_secrets_with(base_dir, strict, |name| std::env::var(name).ok())
+    }
+    /// [`Self::resolve_secrets`] with the `env` secrets looked up from `env` instead of the process environment
+    pub fn resolve_secrets_with(
+        &mut self,
+        base_dir: &Path,
+        strict: bool,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), ConfigError> {
+        if self.secrets.is_empty() {
+            if strict {
+                return Err(ConfigError::SecretError(
+                    "No secret configured for user derived flags".to_string(),
+                ));
+            }
+            tracing::warn!(
+                "No secret configured for user derived flags, using a random one. Flags generated with it cannot be verified after this run."
+            );
+            self.secrets.push(VersionedSecret::new(
+                0,
+                SecretSource::Value(random_hex_secret()),
+            ));
+        }
+        for secret in &mut self.secrets {
+            secret.resolve(base_dir, &env)?;
+        }
+        Ok(())
+    }
+    /// The secret used for generating new flags
+    pub fn active_secret(&self) -> Option<&VersionedSecret> {
+        match self.active {
+            Some(version) => self.secrets.iter().find(|s| s.version == version),
+            None => self.secrets.iter().max_by_key(|s| s.version),
+        }
+    }
+    /// Checks the flag suffix against every configured secret, newest first.
+    /// Returns the version of the secret that produced the flag.
+    pub fn verify(&self, taskid: &str, uuid: &Uuid, suffix: &str) -> Option<u32> {
+        let mut secrets: Vec<&VersionedSecret> = self.secrets.iter().collect();
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.version));
+        secrets.into_iter().find_map(|secret| {
+            let value = secret.value()?;
+            flag_generator::verify_user_flag(&self.algorithm, uuid, value, taskid, suffix)
+                .then_some(secret.version)
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for UserDerived {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // `secret` is the original single secret format, kept for existing configurations
+        #[derive(Deserialize)]
+        struct Helper {
+            #[serde(default = "flag_generator::Algorithm::default")]
+            algorithm: flag_generator::Algorithm,
+            secret: Option<String>,
+            #[serde(default)]
+            secrets: Vec<VersionedSecret>,
+            active: Option<u32>,
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        let mut secrets = helper.secrets;
+        if let Some(secret) = helper.secret {
+            if !secrets.is_empty() {
+                return Err(serde::de::Error::custom(
+                    "Use either 'secret' or 'secrets' for user derived flags, not both",
+                ));
+            }
+            secrets.push(VersionedSecret::new(
+                DEFAULT_SECRET_VERSION,
+                SecretSource::Value(secret),
+            ));
+        }
+        Self::new(helper.algorithm, secrets, helper.active).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Where the value of a secret is read from.
+/// Prefer `env` or `file` so that the secret does not need to be committed with the configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretSource {
+    Value(String),
+    Env(String),
+    File(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VersionedSecret {
+    pub version: u32,
+    #[serde(flatten)]
+    pub source: SecretSource,
+    /// Loaded value for `env` and `file` sources
+    #[serde(skip)]
+    resolved: Option<String>,
+}
+
+impl VersionedSecret {
+    pub fn new(version: u32, source: SecretSource) -> VersionedSecret {
+        VersionedSecret {
+            version,
+            source,
+            resolved: None,
+        }
+    }
+    fn resolve(
+        &mut self,
+        base_dir: &Path,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), ConfigError> {
+        let value = match &self.source {
+            SecretSource::Value(_) => return Ok(()),
+            SecretSource::Env(name) => env(name).ok_or_else(|| {
+                ConfigError::SecretError(format!(
+                    "Environment variable '{name}' for secret version {} is not set",
+                    self.version
+                ))
+            })?,
+            SecretSource::File(path) => {
+                let path = base_dir.join(path);
+                std::fs::read_to_string(&path).map_err(|e| {
+                    ConfigError::SecretError(format!(
+                        "Failed to read secret version {} from '{}': {e}",
+                        self.version,
+                        path.display()
+                    ))
+                })?
+            }
+        };
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return Err(ConfigError::SecretError(format!(
+                "Secret version {} is empty",
+                self.version
+            )));
+        }
+        self.resolved = Some(value);
+        Ok(())
+    }
+    /// Secret value, `None` if an `env` or `file` secret has not been resolved yet
+    pub fn value(&self) -> Option<&str> {
+        match &self.source {
+            SecretSource::Value(value) => Some(value),
+            _ => self.resolved.as_deref(),
         }
     }
 }
 
+impl std::fmt::Debug for VersionedSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VersionedSecret")
+            .field("version", &self.version)
+            .field("source", &self.source)
+            .field("resolved", &self.resolved.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RngSeed {
     pub secret: String,
@@ -524,12 +943,14 @@ impl Default for Upload {
 pub enum Builder {
     Nix(Nix),
     Shell(Shell),
+    Container(Container),
 }
 impl Builder {
     pub const fn to_str(&self) -> &str {
         match self {
             Builder::Nix(_) => "nix",
             Builder::Shell(_) => "shell",
+            Builder::Container(_) => "container",
         }
     }
 }
@@ -572,6 +993,33 @@ impl Default for Shell {
     }
 }
 
+/// Runs the shell entrypoint inside a local container image, see [`crate::container`]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Container {
+    /// Image must be available locally, it is not pulled
+    pub image: String,
+    #[serde(default = "Shell::default_entrypoint")]
+    pub entrypoint: String,
+    #[serde(default)]
+    pub engine: ContainerEngine,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerEngine {
+    #[default]
+    Podman,
+    Docker,
+}
+impl ContainerEngine {
+    pub const fn program(&self) -> &str {
+        match self {
+            ContainerEngine::Podman => "podman",
+            ContainerEngine::Docker => "docker",
+        }
+    }
+}
+
 pub fn check_toml(module: ModuleConfiguration) -> Result<ModuleConfiguration, ConfigError> {
     let module_name = &module.name;
     if module_name.is_empty() {
@@ -633,12 +1081,12 @@ pub fn check_task(task: &Task) -> Result<bool, ConfigError> {
                     "ID cannot be empty if you have more than one stages.",
                 ));
             }
-            if let Some(id) = &part.id {
-                if !id.to_lowercase().starts_with(&task.id.to_lowercase()) {
-                    return Err(ConfigError::StageError(
-                        "Stage ID must be prefixed with task ID",
-                    ));
-                }
+            if let Some(id) = &part.id
+                && !id.to_lowercase().starts_with(&task.id.to_lowercase())
+            {
+                return Err(ConfigError::StageError(
+                    "Stage ID must be prefixed with task ID",
+                ));
             }
         } else if part.id.is_some() {
             // Single element in parts, id must be none
@@ -648,6 +1096,30 @@ pub fn check_task(task: &Task) -> Result<bool, ConfigError> {
         }
     }
 
+    if let Some(sandbox) = &task.build.sandbox {
+        for (name, limit) in [
+            ("cpu_time", sandbox.cpu_time),
+            ("memory", sandbox.memory),
+            ("processes", sandbox.processes),
+        ] {
+            if limit == Some(0) {
+                return Err(ConfigError::SandboxLimitError(task.id.clone(), name));
+            }
+        }
+    }
+
+    if let Some(package) = &task.build.package {
+        package
+            .check_name()
+            .map_err(|error| ConfigError::PackageNameError(task.id.clone(), error))?;
+    }
+
+    if let Some(penalty) = task.moodle.penalty
+        && !(0.0..=1.0).contains(&penalty)
+    {
+        return Err(ConfigError::PenaltyError(task.id.clone(), penalty));
+    }
+
     //checks subtasks have unique id
     if task.stages.len() > 1 {
         let mut set = HashSet::new();
@@ -693,13 +1165,19 @@ pub fn read_check_toml(filepath: &OsStr) -> Result<ModuleConfiguration, ConfigEr
         .map_err(|err| ConfigError::TomlParseError {
             message: format!("Failed to read file content: {err}"),
         })?;
-    let module_config =
+    let mut module_config: ModuleConfiguration =
         toml::from_str(&file_content).map_err(|err| ConfigError::TomlParseError {
             message: err.to_string(),
         })?;
+    module_config.resolve_secrets(config_directory(Path::new(filepath)), false)?;
     check_toml(module_config)
 }
 
+/// Secret files and other relative paths are relative to the directory of the configuration file
+fn config_directory(filepath: &Path) -> &Path {
+    filepath.parent().unwrap_or_else(|| Path::new(""))
+}
+
 pub async fn server_read_check_toml(filepath: &OsStr) -> Result<ModuleConfiguration, ConfigError> {
     let mut file =
         tokio::fs::File::open(filepath)
@@ -714,16 +1192,18 @@ pub async fn server_read_check_toml(filepath: &OsStr) -> Result<ModuleConfigurat
         .map_err(|err| ConfigError::TomlParseError {
             message: format!("Failed to read file content: {err}"),
         })?;
-    let module_config =
+    let mut module_config: ModuleConfiguration =
         toml::from_str(&file_content).map_err(|err| ConfigError::TomlParseError {
             message: err.to_string(),
         })?;
+    // Server cannot fall back to a random secret, flags must stay verifiable over restarts
+    module_config.resolve_secrets(config_directory(Path::new(filepath)), true)?;
     check_toml(module_config)
 }
 
 /// Reading configuration files for server-side where faulty configs are not accepted.
 pub async fn read_toml(filepath: PathBuf) -> Result<ModuleConfiguration, ConfigError> {
-    let mut file = File::open(filepath).map_err(|err| ConfigError::TomlParseError {
+    let mut file = File::open(&filepath).map_err(|err| ConfigError::TomlParseError {
         message: format!("Failed to open file: {err}"),
     })?;
 
@@ -732,17 +1212,26 @@ pub async fn read_toml(filepath: PathBuf) -> Result<ModuleConfiguration, ConfigE
         .map_err(|err| ConfigError::TomlParseError {
             message: format!("Failed to read file content: {err}"),
         })?;
-    let module_config =
+    let mut module_config: ModuleConfiguration =
         toml::from_str(&file_content).map_err(|err| ConfigError::TomlParseError {
             message: err.to_string(),
         })?;
+    module_config.resolve_secrets(config_directory(&filepath), true)?;
     Ok(module_config)
 }
 #[cfg(test)]
 mod tests {
     use insta::assert_debug_snapshot;
 
-    use super::{Deployment, ModuleConfiguration};
+    use super::{
+        ArchiveFormat, Builder, Container, ContainerEngine, Deployment, ModuleConfiguration,
+        MoodleConfig, PackageConfig, SandboxConfig, SecretSource, UserDerived, VersionedSecret,
+        check_task,
+    };
+    use crate::errors::ConfigError;
+    use crate::flag_generator::{Algorithm, Flag};
+    use std::path::Path;
+    use uuid::Uuid;
 
     #[test]
     fn test_toml() {
@@ -776,4 +1265,193 @@ mod tests {
         let result: Deployment = toml::from_str(config).unwrap();
         assert_debug_snapshot!(result);
     }
+
+    #[test]
+    fn test_container_builder() {
+        let builder: Builder = toml::from_str(
+            r#"
+            container = { image = "localhost/gcc:4.9" }
+            "#,
+        )
+        .unwrap();
+        let Builder::Container(container) = builder else {
+            panic!("Expected container builder");
+        };
+        assert_eq!(container.image, "localhost/gcc:4.9");
+        assert_eq!(container.entrypoint, "entrypoint.sh");
+        assert_eq!(container.engine, ContainerEngine::Podman);
+
+        let builder: Builder = toml::from_str(
+            r#"
+            container = { image = "cross", entrypoint = "build.sh", engine = "docker" }
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            builder,
+            Builder::Container(Container {
+                engine: ContainerEngine::Docker,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_sandbox_limits() {
+        let sandbox = include_str!("../tests/data/configs/sandbox.toml");
+        let mut config: ModuleConfiguration = toml::from_str(sandbox).unwrap();
+        let task = &mut config.categories[0].tasks[0];
+        assert_eq!(
+            task.build.sandbox,
+            Some(SandboxConfig {
+                network: false,
+                cpu_time: Some(60),
+                memory: Some(512),
+                processes: Some(4096),
+            })
+        );
+        assert!(check_task(task).is_ok());
+        task.build.sandbox.as_mut().unwrap().memory = Some(0);
+        assert!(matches!(
+            check_task(task),
+            Err(ConfigError::SandboxLimitError(_, "memory"))
+        ));
+    }
+
+    #[test]
+    fn test_moodle_config() {
+        let moodle: MoodleConfig = toml::from_str(
+            r#"
+            name = "{{ task.name }} ({{ uuid }})"
+            penalty = 0.25
+            hints = ["Look at the script"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(moodle.correct_feedback, "Correct!");
+        assert_eq!(moodle.hints, ["Look at the script"]);
+        assert!(moodle.tags.is_empty());
+
+        let default = include_str!("../tests/data/configs/default.toml");
+        let mut config: ModuleConfiguration = toml::from_str(default).unwrap();
+        let task = &mut config.categories[0].tasks[0];
+        task.moodle = moodle;
+        assert!(check_task(task).is_ok());
+        task.moodle.penalty = Some(1.5);
+        assert!(matches!(
+            check_task(task),
+            Err(ConfigError::PenaltyError(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_package_name() {
+        let mut package = PackageConfig {
+            format: ArchiveFormat::TarGz,
+            name: PackageConfig::default_name(),
+        };
+        let uuid = Uuid::parse_str("0192a1b0-0000-7000-8000-00000000abcd").unwrap();
+        assert_eq!(
+            package.file_name("task001", &uuid),
+            "task001-0000abcd.tar.gz"
+        );
+        assert!(package.check_name().is_ok());
+        package.name = "{task}-{uuid}".to_string();
+        assert!(package.check_name().is_err());
+        package.name = "../{uuid}".to_string();
+        assert!(package.check_name().is_err());
+    }
+
+    #[test]
+    fn test_secret_rotation() {
+        let config: ModuleConfiguration =
+            toml::from_str(include_str!("../tests/data/configs/secret_rotation.toml")).unwrap();
+        let mut user_derived = config.flag_config.user_derived;
+        user_derived
+            .resolve_secrets_with(Path::new("tests/data/configs"), true, |name| {
+                (name == "AINIGMA_TEST_ROTATION_SECRET_V2")
+                    .then(|| "Current secret from env\n".to_string())
+            })
+            .unwrap();
+        let active = user_derived.active_secret().unwrap();
+        assert_eq!(active.version, 2);
+        assert_eq!(active.value(), Some("Current secret from env"));
+
+        let uuid = Uuid::now_v7();
+        for (version, secret) in [
+            (1, "Retired secret, flags generated with it are still valid"),
+            (2, "Current secret from env"),
+            (3, "Staged secret for the next rotation"),
+        ] {
+            let flag = Flag::new_user_flag(
+                "task001".to_string(),
+                &Algorithm::HMAC_SHA3_256,
+                secret,
+                "task001",
+                &uuid,
+            );
+            let Flag::UserDerivedFlag(unit) = flag else {
+                unreachable!()
+            };
+            assert_eq!(
+                user_derived.verify("task001", &uuid, unit.value()),
+                Some(version)
+            );
+            assert_eq!(user_derived.verify("task002", &uuid, unit.value()), None);
+        }
+    }
+
+    #[test]
+    fn test_missing_secret() {
+        let config = include_str!("../tests/data/configs/secret_rotation.toml").replace(
+            "AINIGMA_TEST_ROTATION_SECRET_V2",
+            "AINIGMA_TEST_UNSET_SECRET",
+        );
+        let mut result: ModuleConfiguration = toml::from_str(&config).unwrap();
+        assert!(
+            result
+                .resolve_secrets(Path::new("tests/data/configs"), false)
+                .is_err()
+        );
+
+        let mut empty = UserDerived::default();
+        assert!(empty.resolve_secrets(Path::new("."), true).is_err());
+        empty.resolve_secrets(Path::new("."), false).unwrap();
+        assert_eq!(empty.secrets.len(), 1);
+        assert!(empty.active_secret().unwrap().value().is_some());
+    }
+
+    #[test]
+    fn test_secret_versions() {
+        let legacy: UserDerived = toml::from_str(r#"secret = "single""#).unwrap();
+        assert_eq!(legacy.active_secret().unwrap().version, 1);
+        assert_eq!(legacy.active_secret().unwrap().value(), Some("single"));
+
+        let both: Result<UserDerived, _> = toml::from_str(
+            r#"secret = "single"
+secrets = [{ version = 2, value = "other" }]"#,
+        );
+        assert!(both.is_err());
+        let duplicate: Result<UserDerived, _> = toml::from_str(
+            r#"secrets = [{ version = 2, value = "one" }, { version = 2, value = "other" }]"#,
+        );
+        assert!(duplicate.is_err());
+        let unknown_active: Result<UserDerived, _> = toml::from_str(
+            r#"active = 3
+secrets = [{ version = 2, value = "one" }]"#,
+        );
+        assert!(unknown_active.is_err());
+        assert!(
+            UserDerived::new(
+                Algorithm::default(),
+                vec![VersionedSecret::new(1, SecretSource::Env("X".to_string()))],
+                None
+            )
+            .unwrap()
+            .active_secret()
+            .unwrap()
+            .value()
+            .is_none()
+        );
+    }
 }
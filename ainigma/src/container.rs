@@ -0,0 +1,172 @@
+//! Builds inside container images with podman or docker
+//!
+//! The task directory is mounted read-only and the output directories writable, both at the same paths as on the host.
+//! This way the paths in the build manifest and in the environment are valid inside the container as well.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{Container, ContainerEngine, SandboxConfig};
+use crate::errors::BuildError;
+use crate::sandbox::absolute;
+
+/// Create a command which runs the program inside the container image of the task
+///
+/// The sandbox configuration of the task, if any, disables the network and sets the limits of the container.
+pub fn container_command(
+    container: &Container,
+    sandbox: Option<&SandboxConfig>,
+    program: &str,
+    args: &[&str],
+    work_dir: &Path,
+    writable_dirs: &[PathBuf],
+    build_envs: HashMap<String, String>,
+) -> Result<Command, BuildError> {
+    let work_dir = absolute(work_dir)?;
+    let writable_dirs = writable_dirs
+        .iter()
+        .map(|dir| absolute(dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut env_names: Vec<&String> = build_envs.keys().collect();
+    env_names.sort();
+    let mut command = Command::new(container.engine.program());
+    command
+        .args(run_args(
+            container,
+            sandbox,
+            &work_dir,
+            &writable_dirs,
+            &env_names,
+        ))
+        .arg(&container.image)
+        .arg(program)
+        .args(args)
+        // Values are read from the environment of the engine, so the flags do not show up in the process list
+        .envs(&build_envs)
+        .current_dir(&work_dir);
+    Ok(command)
+}
+
+fn run_args(
+    container: &Container,
+    sandbox: Option<&SandboxConfig>,
+    work_dir: &Path,
+    writable_dirs: &[PathBuf],
+    env_names: &[&String],
+) -> Vec<String> {
+    let mut args: Vec<String> = ["run", "--rm", "--pull", "never"].map(String::from).into();
+    let work_dir = work_dir.display().to_string();
+    args.extend(["--volume".to_string(), format!("{work_dir}:{work_dir}:ro")]);
+    // Output directories can be inside the task directory, so they must be mounted after it
+    for dir in writable_dirs {
+        let dir = dir.display().to_string();
+        args.extend(["--volume".to_string(), format!("{dir}:{dir}")]);
+    }
+    args.extend(["--workdir".to_string(), work_dir]);
+    if container.engine == ContainerEngine::Docker {
+        // Rootless podman maps the container root to the current user, docker needs this for the output file ownership
+        // SAFETY: getuid and getgid are always successful and have no side effects
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        args.extend(["--user".to_string(), format!("{uid}:{gid}")]);
+    }
+    for name in env_names {
+        args.extend(["--env".to_string(), name.to_string()]);
+    }
+    if let Some(sandbox) = sandbox {
+        if !sandbox.network {
+            args.extend(["--network", "none"].map(String::from));
+        }
+        if let Some(seconds) = sandbox.cpu_time {
+            args.extend(["--ulimit".to_string(), format!("cpu={seconds}")]);
+        }
+        if let Some(megabytes) = sandbox.memory {
+            args.extend(["--memory".to_string(), format!("{megabytes}m")]);
+        }
+        if let Some(processes) = sandbox.processes {
+            args.extend(["--pids-limit".to_string(), processes.to_string()]);
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(engine: ContainerEngine) -> Container {
+        Container {
+            image: "localhost/gcc:4.9".to_string(),
+            entrypoint: "entrypoint.sh".to_string(),
+            engine,
+        }
+    }
+
+    #[test]
+    fn test_run_args() {
+        let task_001 = "TASK_ID".to_string();
+        let args = run_args(
+            &container(ContainerEngine::Podman),
+            None,
+            Path::new("/tasks/task001"),
+            &[PathBuf::from("/out/uuid/task001")],
+            &[&task_001],
+        );
+        let joined = args.join(" ");
+        assert!(joined.starts_with("run --rm --pull never"));
+        assert!(joined.contains("--volume /tasks/task001:/tasks/task001:ro"));
+        assert!(joined.contains("--volume /out/uuid/task001:/out/uuid/task001 "));
+        assert!(joined.contains("--workdir /tasks/task001"));
+        assert!(joined.ends_with("--env TASK_ID"));
+        assert!(!joined.contains("--network"));
+        assert!(!joined.contains("--user"));
+
+        let sandbox = SandboxConfig {
+            network: false,
+            cpu_time: Some(60),
+            memory: Some(512),
+            processes: Some(64),
+        };
+        let args = run_args(
+            &container(ContainerEngine::Docker),
+            Some(&sandbox),
+            Path::new("/tasks/task001"),
+            &[],
+            &[],
+        );
+        let joined = args.join(" ");
+        assert!(joined.contains("--user "));
+        assert!(joined.contains("--network none"));
+        assert!(joined.contains("--ulimit cpu=60"));
+        assert!(joined.contains("--memory 512m"));
+        assert!(joined.contains("--pids-limit 64"));
+    }
+
+    #[test]
+    fn test_container_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = container_command(
+            &container(ContainerEngine::Docker),
+            None,
+            "sh",
+            &["entrypoint.sh"],
+            dir.path(),
+            &[dir.path().to_path_buf()],
+            HashMap::from([("FLAG_PURE_RANDOM_TASK001".to_string(), "secret".to_string())]),
+        )
+        .unwrap();
+        assert_eq!(command.get_program(), "docker");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            &args[args.len() - 3..],
+            ["localhost/gcc:4.9", "sh", "entrypoint.sh"]
+        );
+        // Flag value is only passed in the environment
+        assert!(
+            !args
+                .iter()
+                .any(|arg| arg.to_string_lossy().contains("secret"))
+        );
+        assert_eq!(command.get_envs().count(), 1);
+    }
+}
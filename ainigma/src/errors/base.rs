@@ -46,6 +46,8 @@ pub enum CloudStorageError {
     // upload error
     #[error("Failed to upload file: {0}")]
     UploadError(String),
+    #[error("Uploaded file does not match the local file: {0}")]
+    IntegrityError(String),
 }
 
 #[derive(Error, Debug)]
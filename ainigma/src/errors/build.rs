@@ -23,6 +23,26 @@ pub enum BuildError {
     StageHadNoBatch(String),
     #[error("Task with ID {0} not found in module configuration")]
     TaskNotFound(String),
+    #[error("Flag secret is not available: {0}")]
+    FlagSecretError(String),
+    #[error("Sandboxed build failed: {0}")]
+    SandboxError(String),
+    #[error("Build cache failed: {0}")]
+    CacheError(String),
+    #[error("Solver verification failed: {0}")]
+    SolverError(String),
+    #[error("Flag found in the files given to the students: {0}")]
+    FlagLeak(String),
+    #[error("Build cannot be reproduced: {0}")]
+    NotReproducible(String),
+    #[error("Build was cancelled: {0}")]
+    Cancelled(String),
+    #[error("Packaging the resource files failed: {0}")]
+    PackagingError(String),
+    #[error("Readme template cannot be rendered: {0}")]
+    TemplateError(String),
+    #[error("Build manifest cannot be used: {0}")]
+    ManifestError(String),
 }
 
 impl From<serde_json::Error> for BuildError {
@@ -32,4 +32,12 @@ pub enum ConfigError {
     BuildModeError(String, String),
     #[error("Error reading file {0}")]
     FileReadError(String),
+    #[error("Flag secret incorrectly configured: {0}")]
+    SecretError(String),
+    #[error("Sandbox limits must be greater than zero, task {0} has {1} set to zero")]
+    SandboxLimitError(String, &'static str),
+    #[error("Package name of task {0} is invalid: {1}")]
+    PackageNameError(String, String),
+    #[error("Question penalty must be between 0 and 1, task {0} has {1}")]
+    PenaltyError(String, f32),
 }
@@ -0,0 +1,132 @@
+//! Structured events of the build process
+//!
+//! The builds report their progress to an [`EventSink`] given in the build options.
+//! The events are emitted from the thread running the build, so a sink shared by parallel builds must be thread-safe.
+use std::fmt;
+use std::sync::Arc;
+use std::sync::mpsc;
+use uuid::Uuid;
+
+/// Progress of a single variant, or of the whole task in batch builds
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BuildEvent {
+    VariantStarted {
+        task_id: String,
+        uuid: Uuid,
+    },
+    /// A line the builder printed to stdout. Batch builds have no UUID.
+    BuilderOutput {
+        task_id: String,
+        uuid: Option<Uuid>,
+        line: String,
+    },
+    /// All the expected output files exist and the flags were collected
+    OutputsValidated {
+        task_id: String,
+        uuid: Uuid,
+    },
+    VariantFinished {
+        task_id: String,
+        uuid: Uuid,
+        /// The outputs were copied from the build cache
+        cached: bool,
+    },
+    /// The files of the variant were uploaded to the cloud storage
+    UploadFinished {
+        task_id: String,
+        uuid: Uuid,
+        files: usize,
+    },
+    Failed {
+        task_id: String,
+        uuid: Option<Uuid>,
+        error: String,
+    },
+}
+
+type Callback = dyn Fn(&BuildEvent) + Send + Sync;
+
+/// Receiver of the build events. The default sink discards them.
+#[derive(Clone, Default)]
+pub struct EventSink(Option<Arc<Callback>>);
+
+impl EventSink {
+    /// Call the function for every event
+    pub fn new(callback: impl Fn(&BuildEvent) + Send + Sync + 'static) -> Self {
+        Self(Some(Arc::new(callback)))
+    }
+
+    /// Send the events to a channel. The sending stops silently when the receiver is dropped.
+    pub fn channel() -> (Self, mpsc::Receiver<BuildEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let sink = Self::new(move |event| {
+            let _ = sender.send(event.clone());
+        });
+        (sink, receiver)
+    }
+
+    pub fn emit(&self, event: BuildEvent) {
+        if let Some(callback) = &self.0 {
+            callback(&event);
+        }
+    }
+
+    /// Whether anyone receives the events, to avoid creating events for nobody
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EventSink")
+            .field(&if self.is_enabled() {
+                "enabled"
+            } else {
+                "disabled"
+            })
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serialization() {
+        let event = BuildEvent::BuilderOutput {
+            task_id: "task001".to_string(),
+            uuid: None,
+            line: "Building...".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"builder_output","task_id":"task001","uuid":null,"line":"Building..."}"#
+        );
+    }
+
+    #[test]
+    fn test_channel() {
+        let (sink, receiver) = EventSink::channel();
+        let uuid = Uuid::now_v7();
+        sink.emit(BuildEvent::VariantStarted {
+            task_id: "task001".to_string(),
+            uuid,
+        });
+        drop(sink);
+        let events: Vec<_> = receiver.iter().collect();
+        assert_eq!(
+            events,
+            [BuildEvent::VariantStarted {
+                task_id: "task001".to_string(),
+                uuid
+            }]
+        );
+        // Emitting to a dropped receiver is not an error
+        let (sink, receiver) = EventSink::channel();
+        drop(receiver);
+        sink.emit(events[0].clone());
+    }
+}
@@ -0,0 +1,143 @@
+//! Writing the exam questions in the formats of different learning management systems
+//!
+//! The questions are built once by [`crate::moodle::create_exam`] and each [`Exporter`] only decides how they are
+//! written, so every format has the same instructions and answers. [`ctfd`] exports the builds as challenges instead.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use crate::build_process::Challenge;
+use crate::moodle::quiz::ShortAnswerQuestion;
+
+pub mod csv;
+pub mod ctfd;
+pub mod gift;
+pub mod moodle_xml;
+pub mod qti;
+
+/// File linked from the question
+#[derive(Debug, Clone, PartialEq)]
+pub struct Download {
+    pub name: String,
+    pub link: String,
+}
+
+/// Question of a single variant
+///
+/// The files embedded into the question are linked with [`PLUGINFILE`](crate::moodle::quiz::PLUGINFILE) followed by
+/// their path. Formats which store the files elsewhere replace the prefix with the location of the files.
+#[derive(Debug, Clone)]
+pub struct VariantQuestion {
+    pub uuid: Uuid,
+    /// Instructions of the variant as Markdown
+    pub instructions: String,
+    /// Meta values shown below the instructions, not set when a readme template places them
+    pub meta: Option<Challenge>,
+    /// Links shown below the instructions, empty when a readme template places them
+    pub downloads: Vec<Download>,
+    /// The question with the instructions, meta values and links as HTML, the embedded files and the answers
+    pub question: ShortAnswerQuestion,
+}
+
+/// Writer of the questions in some format
+pub trait Exporter {
+    /// Write the questions of the category into the output
+    fn write(
+        &self,
+        category: &str,
+        questions: &[VariantQuestion],
+        output: &mut dyn Write,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// Whether the format can contain the files embedded into the questions
+    fn supports_files(&self) -> bool {
+        false
+    }
+}
+
+/// Output format of the exam
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Moodle XML, which supports all features of the questions
+    #[default]
+    MoodleXml,
+    /// GIFT, a text format supported by Moodle and other systems
+    Gift,
+    /// One row for each accepted answer, for reviewing the answers
+    Csv,
+    /// IMS QTI 2.1 package for Canvas, Blackboard and other systems, with the files in the package
+    Qti,
+}
+
+impl ExportFormat {
+    pub fn exporter(&self) -> Box<dyn Exporter> {
+        match self {
+            ExportFormat::MoodleXml => Box::new(moodle_xml::MoodleXml),
+            ExportFormat::Gift => Box::new(gift::Gift),
+            ExportFormat::Csv => Box::new(csv::Csv),
+            ExportFormat::Qti => Box::new(qti::Qti),
+        }
+    }
+    /// Formats which always contain the files which were not uploaded embed them up to this size
+    pub fn bundled_file_limit(&self) -> Option<u64> {
+        match self {
+            ExportFormat::Qti => Some(u64::MAX),
+            _ => None,
+        }
+    }
+    /// Extension of the output file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::MoodleXml => "xml",
+            ExportFormat::Gift => "txt",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Qti => "zip",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::MoodleXml => "moodle-xml",
+            ExportFormat::Gift => "gift",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Qti => "qti",
+        })
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "moodle-xml" | "xml" => Ok(ExportFormat::MoodleXml),
+            "gift" => Ok(ExportFormat::Gift),
+            "csv" => Ok(ExportFormat::Csv),
+            "qti" => Ok(ExportFormat::Qti),
+            other => Err(format!(
+                "unknown format '{other}', expected moodle-xml, gift, csv or qti"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_names() {
+        for format in [
+            ExportFormat::MoodleXml,
+            ExportFormat::Gift,
+            ExportFormat::Csv,
+            ExportFormat::Qti,
+        ] {
+            assert_eq!(format.to_string().parse::<ExportFormat>(), Ok(format));
+        }
+        assert!("aiken".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! CSV with one row for each accepted answer: `question,uuid,answer,fraction`
+use std::io::Write;
+
+use super::{Exporter, VariantQuestion};
+
+pub struct Csv;
+
+impl Exporter for Csv {
+    fn write(
+        &self,
+        _category: &str,
+        questions: &[VariantQuestion],
+        output: &mut dyn Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = ::csv::Writer::from_writer(output);
+        writer.write_record(["question", "uuid", "answer", "fraction"])?;
+        for variant in questions {
+            let uuid = variant.uuid.to_string();
+            for answer in &variant.question.answers {
+                writer.write_record([
+                    variant.question.name.as_str(),
+                    uuid.as_str(),
+                    answer.text.as_str(),
+                    answer.fraction.to_string().as_str(),
+                ])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moodle::quiz::{Answer, ShortAnswerQuestion};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_csv() {
+        let mut question = ShortAnswerQuestion::new("Task, part 1".to_string(), String::new());
+        question.answers = vec![
+            Answer::new(100, "flag{a b}".to_string(), None),
+            Answer::new(50, "a".to_string(), None),
+        ];
+        let questions = [VariantQuestion {
+            uuid: Uuid::nil(),
+            instructions: String::new(),
+            meta: None,
+            downloads: Vec::new(),
+            question,
+        }];
+        let mut output = Vec::new();
+        Csv.write("exam", &questions, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "question,uuid,answer,fraction\n\
+             \"Task, part 1\",00000000-0000-0000-0000-000000000000,flag{a b},100\n\
+             \"Task, part 1\",00000000-0000-0000-0000-000000000000,a,50\n"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Challenges for [ctfcli](https://github.com/CTFd/ctfcli), the command line tool of CTFd
+//!
+//! Each built task becomes a directory with a `challenge.yml` and the distributed files under `dist/`, which
+//! `ctf challenge install` adds to a running CTFd instance without touching its other challenges. A CTFd import
+//! archive is not written, because importing a backup replaces all the data of the instance. The challenge is a
+//! standard one in the category of the task with a static flag. Any flag of a challenge solves it in CTFd, so a task
+//! with several stages becomes a challenge for each stage, named by the stage ID and worth the points of the stage.
+//! CTFd gives the same challenge to every player, so one variant of each task is exported.
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::build_process::{IntermediateOutput, TaskBuildContainer, VariantStatus};
+use crate::config::{FlagVariantKind, ModuleConfiguration};
+use crate::flag_generator::Flag;
+use crate::package::entry_name;
+use crate::template;
+
+/// Name of the challenge specification read by ctfcli
+pub const CHALLENGE_FILE: &str = "challenge.yml";
+/// Directory of the distributed files next to [`CHALLENGE_FILE`]
+pub const DIST_DIRECTORY: &str = "dist";
+
+/// Contents of `challenge.yml`, see
+/// <https://github.com/CTFd/ctfcli/blob/master/ctfcli/spec/challenge-example.yml>
+#[derive(Debug, PartialEq)]
+struct Challenge {
+    name: String,
+    category: String,
+    description: String,
+    connection_info: Option<String>,
+    value: u32,
+    flag: String,
+    /// Paths relative to the challenge directory
+    files: Vec<String>,
+}
+
+impl Challenge {
+    /// The specification as YAML
+    ///
+    /// The strings are written as JSON strings, which YAML accepts as double-quoted scalars, so they need no other
+    /// escaping.
+    fn to_yaml(&self) -> String {
+        let quote = |value: &str| serde_json::Value::from(value).to_string();
+        let mut yaml = String::new();
+        let _ = writeln!(yaml, "name: {}", quote(&self.name));
+        let _ = writeln!(yaml, "category: {}", quote(&self.category));
+        let _ = writeln!(yaml, "description: {}", quote(&self.description));
+        if let Some(connection_info) = &self.connection_info {
+            let _ = writeln!(yaml, "connection_info: {}", quote(connection_info));
+        }
+        let _ = writeln!(yaml, "value: {}", self.value);
+        yaml.push_str("type: standard\nstate: visible\nversion: \"0.1\"\n");
+        let _ = writeln!(yaml, "flags:\n  - {}", quote(&self.flag));
+        if !self.files.is_empty() {
+            yaml.push_str("files:\n");
+            for file in &self.files {
+                let _ = writeln!(yaml, "  - {}", quote(file));
+            }
+        }
+        yaml
+    }
+}
+
+/// Challenge of a task or a stage before it is written
+struct ExportedChallenge {
+    /// Directory of the challenge, the task ID or the stage ID
+    directory: String,
+    challenge: Challenge,
+    /// Path relative to the challenge directory and the file to copy there
+    files: Vec<(String, PathBuf)>,
+}
+
+impl ExportedChallenge {
+    /// The challenges of the task, one for each stage
+    fn for_task(
+        category: &str,
+        build: &TaskBuildContainer,
+    ) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+        let task = &build.task;
+        let Some(instance) = exported_variant(build) else {
+            tracing::warn!("Task '{}' has no built variants to export", task.id);
+            return Ok(Vec::new());
+        };
+        if task
+            .stages
+            .iter()
+            .any(|stage| stage.flag.kind == FlagVariantKind::UserDerived)
+        {
+            tracing::warn!(
+                "Task '{}' has user derived flags, but every player gets the flags of variant {}",
+                task.id,
+                instance.uuid
+            );
+        }
+        let instructions = template::instructions(task, &task.build.directory, instance)?;
+        let files: Vec<(String, PathBuf)> = instance
+            .get_distributed_files()
+            .iter()
+            .map(|item| {
+                let path = instance.task_instance_dir.join(item.kind.get_filename());
+                let name = entry_name(&instance.task_instance_dir, &path);
+                (format!("{DIST_DIRECTORY}/{name}"), path)
+            })
+            .collect();
+        let single = task.stages.len() == 1;
+        let challenges = task
+            .stages
+            .iter()
+            .zip(&instance.stage_flags)
+            .zip(task.stage_points())
+            .map(|((stage, flag), points)| {
+                let (directory, name, description) = match (&stage.id, single) {
+                    (Some(id), false) => {
+                        let description = match &stage.description {
+                            Some(description) => format!("{instructions}\n\n{description}"),
+                            None => instructions.clone(),
+                        };
+                        let name = stage.name.as_deref().unwrap_or(id);
+                        (id.clone(), format!("{}: {name}", task.name), description)
+                    }
+                    _ => (task.id.clone(), task.name.clone(), instructions.clone()),
+                };
+                let flag = match flag {
+                    // The builder has made the flag from the seed
+                    Flag::RngSeed(flag) => flag.value().to_string(),
+                    flag => flag.encased().to_string(),
+                };
+                Self {
+                    directory,
+                    challenge: Challenge {
+                        name,
+                        category: category.to_string(),
+                        description,
+                        connection_info: instance.meta.as_ref().and_then(|meta| meta.url.clone()),
+                        value: points.round() as u32,
+                        flag,
+                        files: files.iter().map(|(name, _)| name.clone()).collect(),
+                    },
+                    files: files.clone(),
+                }
+            })
+            .collect();
+        Ok(challenges)
+    }
+
+    fn write(&self, output: &Path) -> io::Result<()> {
+        let directory = output.join(&self.directory);
+        fs::create_dir_all(&directory)?;
+        for (name, source) in &self.files {
+            let target = directory.join(name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(source, target)?;
+        }
+        fs::write(directory.join(CHALLENGE_FILE), self.challenge.to_yaml())
+    }
+}
+
+/// The variant given to every player: the first built one
+fn exported_variant<'b>(build: &'b TaskBuildContainer) -> Option<&'b IntermediateOutput> {
+    let mut built = build
+        .outputs
+        .iter()
+        .filter(|instance| instance.status == VariantStatus::Built);
+    let instance = built.next()?;
+    let others = built.count();
+    if others > 0 {
+        tracing::warn!(
+            "Task '{}' has {} other variants, only variant {} is exported",
+            build.task.id,
+            others,
+            instance.uuid
+        );
+    }
+    Some(instance)
+}
+
+/// Write a ctfcli challenge directory for each of the built tasks or their stages into `output`
+///
+/// Each of them is installed with `ctf challenge install <output>/<directory>` in a ctfcli project.
+pub fn write_challenges(
+    config: &ModuleConfiguration,
+    builds: &[TaskBuildContainer],
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut challenges = Vec::new();
+    for build in builds {
+        let category = config
+            .get_category_by_task_id(&build.task.id)
+            .ok_or_else(|| {
+                io::Error::other(format!(
+                    "Task '{}' is not in the configuration",
+                    build.task.id
+                ))
+            })?;
+        challenges.extend(ExportedChallenge::for_task(&category.name, build)?);
+    }
+    if challenges.is_empty() {
+        return Err(io::Error::other("There are no built tasks to export").into());
+    }
+    for challenge in &challenges {
+        challenge.write(output)?;
+    }
+    tracing::info!(
+        "Exported {} challenges with {} files into '{}'",
+        challenges.len(),
+        challenges
+            .iter()
+            .map(|challenge| challenge.files.len())
+            .sum::<usize>(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_yaml() {
+        let challenge = Challenge {
+            name: "Task \"one\"".to_string(),
+            category: "Basics".to_string(),
+            description: "Find the flag:\n\n    nc host 1234\n".to_string(),
+            connection_info: None,
+            value: 2,
+            flag: "flag{task001:abc}".to_string(),
+            files: vec!["dist/files/data.txt".to_string()],
+        };
+        let expected = r#"name: "Task \"one\""
+category: "Basics"
+description: "Find the flag:\n\n    nc host 1234\n"
+value: 2
+type: standard
+state: visible
+version: "0.1"
+flags:
+  - "flag{task001:abc}"
+files:
+  - "dist/files/data.txt"
+"#;
+        assert_eq!(challenge.to_yaml(), expected);
+    }
+}
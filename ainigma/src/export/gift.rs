@@ -0,0 +1,101 @@
+//! GIFT, see <https://docs.moodle.org/en/GIFT_format>
+//!
+//! The question text is HTML on a single line, and the answers are short answers with their fractions.
+use std::io::Write;
+
+use super::{Exporter, VariantQuestion};
+
+pub struct Gift;
+
+impl Exporter for Gift {
+    fn write(
+        &self,
+        category: &str,
+        questions: &[VariantQuestion],
+        output: &mut dyn Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        writeln!(output, "$CATEGORY: $course$/{category}")?;
+        for variant in questions {
+            let question = &variant.question;
+            let answers: Vec<String> = question
+                .answers
+                .iter()
+                .map(|answer| {
+                    let fraction = if answer.fraction == 100 {
+                        String::new()
+                    } else {
+                        format!("%{}%", answer.fraction)
+                    };
+                    let feedback = answer
+                        .feedback
+                        .as_deref()
+                        .map(|feedback| format!("#{}", escape(feedback)))
+                        .unwrap_or_default();
+                    format!("={}{}{}", fraction, escape(&answer.text), feedback)
+                })
+                .collect();
+            writeln!(output)?;
+            writeln!(output, "// {}", variant.uuid)?;
+            writeln!(
+                output,
+                "::{}::[html]{}{{{}}}",
+                escape(&question.name),
+                escape(&question.text),
+                answers.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Escape the special characters of GIFT, and the line breaks which would end the question
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '~' | '=' | '#' | '{' | '}' | ':' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moodle::quiz::{Answer, ShortAnswerQuestion};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_gift() {
+        let mut question = ShortAnswerQuestion::new(
+            "Task: 1".to_string(),
+            "<p>Find the flag</p>\n<p>a=b</p>".to_string(),
+        );
+        question.answers = vec![
+            Answer::new(100, "flag{1}".to_string(), Some("Correct!".to_string())),
+            Answer::new(50, "half".to_string(), None),
+        ];
+        let questions = [VariantQuestion {
+            uuid: Uuid::nil(),
+            instructions: String::new(),
+            meta: None,
+            downloads: Vec::new(),
+            question,
+        }];
+        let mut output = Vec::new();
+        Gift.write("exam", &questions, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "$CATEGORY: $course$/exam\n\
+             \n\
+             // 00000000-0000-0000-0000-000000000000\n\
+             ::Task\\: 1::[html]<p>Find the flag</p>\\n<p>a\\=b</p>{=flag\\{1\\}#Correct! =%50%half}\n"
+        );
+    }
+}
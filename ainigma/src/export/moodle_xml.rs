@@ -0,0 +1,29 @@
+//! Moodle XML, see [`crate::moodle::quiz`]
+use std::io::Write;
+
+use super::{Exporter, VariantQuestion};
+use crate::moodle::quiz::Quiz;
+
+pub struct MoodleXml;
+
+impl Exporter for MoodleXml {
+    fn write(
+        &self,
+        category: &str,
+        questions: &[VariantQuestion],
+        output: &mut dyn Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut quiz = Quiz::new(
+            questions
+                .iter()
+                .map(|variant| variant.question.clone())
+                .collect(),
+        );
+        quiz.category = Some(category.to_string());
+        quiz.write(output)?;
+        Ok(())
+    }
+    fn supports_files(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,443 @@
+//! IMS QTI 2.1 package, see <https://www.imsglobal.org/question/qtiv2p1/imsqti_infov2p1.html>
+//!
+//! The package has an item for each variant with a text entry interaction for the flags, and the embedded files of the
+//! variant in a directory named after the item. The instructions are converted from Markdown to the XHTML subset of
+//! QTI, so raw HTML in the Markdown is left out.
+use std::io::{Cursor, Write};
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+use zip::write::SimpleFileOptions;
+
+use super::{Exporter, VariantQuestion};
+use crate::moodle::quiz::{Answer, PLUGINFILE};
+
+const QTI_NAMESPACE: &str = "http://www.imsglobal.org/xsd/imsqti_v2p1";
+const QTI_SCHEMA_LOCATION: &str = "http://www.imsglobal.org/xsd/imsqti_v2p1 http://www.imsglobal.org/xsd/qti/qtiv2p1/imsqti_v2p1.xsd";
+const CP_NAMESPACE: &str = "http://www.imsglobal.org/xsd/imscp_v1p1";
+const CP_SCHEMA_LOCATION: &str = "http://www.imsglobal.org/xsd/imscp_v1p1 http://www.imsglobal.org/xsd/qti/qtiv2p1/qtiv2p1_imscpv1p2_v1p0.xsd";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+/// Response processing which sums the mapped values of the response
+const MAP_RESPONSE: &str = "http://www.imsglobal.org/question/qti_v2p1/rptemplates/map_response";
+const RESPONSE: &str = "RESPONSE";
+
+pub struct Qti;
+
+impl Exporter for Qti {
+    fn write(
+        &self,
+        _category: &str,
+        questions: &[VariantQuestion],
+        output: &mut dyn Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut package = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip::DateTime::default());
+        let mut resources = Vec::with_capacity(questions.len());
+        for variant in questions {
+            let identifier = format!("item-{}", variant.uuid);
+            let href = format!("{identifier}.xml");
+            package.start_file(href.as_str(), options)?;
+            write_item(&mut package, &identifier, variant)?;
+            let mut files = vec![href];
+            for file in &variant.question.files {
+                package.start_file(format!("{}{}{}", identifier, file.path, file.name), options)?;
+                package.write_all(&file.contents)?;
+                files.push(format!("{}/{}", identifier, file.relative_link()));
+            }
+            resources.push((identifier, files));
+        }
+        package.start_file("imsmanifest.xml", options)?;
+        write_manifest(&mut package, &resources)?;
+        output.write_all(package.finish()?.get_ref())?;
+        Ok(())
+    }
+    fn supports_files(&self) -> bool {
+        true
+    }
+}
+
+/// Content package manifest listing the items and their files
+fn write_manifest<W: Write>(
+    output: W,
+    resources: &[(String, Vec<String>)],
+) -> xml::writer::Result<()> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(output);
+    writer.write(
+        XmlEvent::start_element("manifest")
+            .default_ns(CP_NAMESPACE)
+            .ns("xsi", XSI_NAMESPACE)
+            .attr("xsi:schemaLocation", CP_SCHEMA_LOCATION)
+            .attr("identifier", "MANIFEST-1"),
+    )?;
+    writer.write(XmlEvent::start_element("metadata"))?;
+    write_text_element(&mut writer, "schema", "QTIv2.1 Package")?;
+    write_text_element(&mut writer, "schemaversion", "1.0.0")?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::start_element("organizations"))?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::start_element("resources"))?;
+    for (identifier, files) in resources {
+        writer.write(
+            XmlEvent::start_element("resource")
+                .attr("identifier", identifier)
+                .attr("type", "imsqti_item_xmlv2p1")
+                .attr("href", &files[0]),
+        )?;
+        for file in files {
+            writer.write(XmlEvent::start_element("file").attr("href", file))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn write_item<W: Write>(
+    output: W,
+    identifier: &str,
+    variant: &VariantQuestion,
+) -> xml::writer::Result<()> {
+    let question = &variant.question;
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(output);
+    writer.write(
+        XmlEvent::start_element("assessmentItem")
+            .default_ns(QTI_NAMESPACE)
+            .ns("xsi", XSI_NAMESPACE)
+            .attr("xsi:schemaLocation", QTI_SCHEMA_LOCATION)
+            .attr("identifier", identifier)
+            .attr("title", &question.name)
+            .attr("adaptive", "false")
+            .attr("timeDependent", "false"),
+    )?;
+
+    let answers = mapped_answers(&question.answers);
+    writer.write(
+        XmlEvent::start_element("responseDeclaration")
+            .attr("identifier", RESPONSE)
+            .attr("cardinality", "single")
+            .attr("baseType", "string"),
+    )?;
+    if let Some((correct, _)) = answers.iter().find(|(_, fraction)| *fraction >= 100) {
+        writer.write(XmlEvent::start_element("correctResponse"))?;
+        write_text_element(&mut writer, "value", correct)?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::start_element("mapping").attr("defaultValue", "0"))?;
+    let case_sensitive = question.use_case.to_string();
+    for (text, fraction) in &answers {
+        let value = (f64::from(*fraction) / 100.0).to_string();
+        writer.write(
+            XmlEvent::start_element("mapEntry")
+                .attr("mapKey", text)
+                .attr("mappedValue", &value)
+                .attr("caseSensitive", &case_sensitive),
+        )?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+
+    writer.write(
+        XmlEvent::start_element("outcomeDeclaration")
+            .attr("identifier", "SCORE")
+            .attr("cardinality", "single")
+            .attr("baseType", "float"),
+    )?;
+    writer.write(XmlEvent::start_element("defaultValue"))?;
+    write_text_element(&mut writer, "value", "0")?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+
+    // The embedded files are next to the item
+    let files = format!("{identifier}/");
+    writer.write(XmlEvent::start_element("itemBody"))?;
+    write_markdown(
+        &mut writer,
+        &variant
+            .instructions
+            .replace(&format!("{PLUGINFILE}/"), &files),
+    )?;
+    if let Some(meta) = &variant.meta {
+        if let Some(url) = meta.url.as_deref().filter(|url| is_safe_url(url)) {
+            writer.write(XmlEvent::start_element("p"))?;
+            writer.write(XmlEvent::characters("Address of your challenge: "))?;
+            write_link(&mut writer, url, url)?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        if !meta.metadata.is_empty() {
+            writer.write(XmlEvent::start_element("ul"))?;
+            for (key, value) in &meta.metadata {
+                let value = match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                writer.write(XmlEvent::start_element("li"))?;
+                write_text_element(&mut writer, "strong", key)?;
+                writer.write(XmlEvent::characters(&format!(": {value}")))?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            writer.write(XmlEvent::end_element())?;
+        }
+    }
+    if !variant.downloads.is_empty() {
+        write_text_element(&mut writer, "p", "Please, see the download links below.")?;
+        writer.write(XmlEvent::start_element("ul"))?;
+        for download in &variant.downloads {
+            let link = download.link.replace(&format!("{PLUGINFILE}/"), &files);
+            writer.write(XmlEvent::start_element("li"))?;
+            write_link(&mut writer, &link, &download.name)?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+    let expected_length = answers
+        .iter()
+        .map(|(text, _)| text.chars().count())
+        .max()
+        .unwrap_or_default()
+        .to_string();
+    writer.write(XmlEvent::start_element("p"))?;
+    writer.write(
+        XmlEvent::start_element("textEntryInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .attr("expectedLength", &expected_length),
+    )?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+
+    writer.write(XmlEvent::start_element("responseProcessing").attr("template", MAP_RESPONSE))?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// The keys of a mapping are unique, so each answer text keeps its highest fraction. Unmapped responses are worth
+/// nothing, so answers without points, such as the catch-all of the incorrect feedback, are left out.
+fn mapped_answers(answers: &[Answer]) -> Vec<(&str, i8)> {
+    let mut mapped: Vec<(&str, i8)> = Vec::with_capacity(answers.len());
+    for answer in answers.iter().filter(|answer| answer.fraction > 0) {
+        match mapped
+            .iter_mut()
+            .find(|(text, _)| *text == answer.text.as_str())
+        {
+            Some((_, fraction)) => *fraction = (*fraction).max(answer.fraction),
+            None => mapped.push((answer.text.as_str(), answer.fraction)),
+        }
+    }
+    mapped
+}
+
+fn write_text_element<W: Write>(
+    writer: &mut EventWriter<W>,
+    name: &str,
+    text: &str,
+) -> xml::writer::Result<()> {
+    writer.write(XmlEvent::start_element(name))?;
+    writer.write(XmlEvent::characters(text))?;
+    writer.write(XmlEvent::end_element())
+}
+
+fn write_link<W: Write>(
+    writer: &mut EventWriter<W>,
+    href: &str,
+    text: &str,
+) -> xml::writer::Result<()> {
+    writer.write(XmlEvent::start_element("a").attr("href", href))?;
+    writer.write(XmlEvent::characters(text))?;
+    writer.write(XmlEvent::end_element())
+}
+
+/// Links are relative, or use a scheme which cannot run scripts
+fn is_safe_url(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => {
+            matches!(
+                scheme.to_ascii_lowercase().as_str(),
+                "http" | "https" | "mailto"
+            )
+        }
+        _ => true,
+    }
+}
+
+fn heading(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "h1",
+        HeadingLevel::H2 => "h2",
+        HeadingLevel::H3 => "h3",
+        HeadingLevel::H4 => "h4",
+        HeadingLevel::H5 => "h5",
+        HeadingLevel::H6 => "h6",
+    }
+}
+
+/// Write the Markdown as the XHTML elements of QTI
+fn write_markdown<W: Write>(
+    writer: &mut EventWriter<W>,
+    markdown: &str,
+) -> xml::writer::Result<()> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    // The number of elements written for each open Markdown tag, closed at the end of the tag
+    let mut open: Vec<usize> = Vec::new();
+    let mut table_head = false;
+    let mut table_body = false;
+    // Source and the alternative text of the image being read
+    let mut image: Option<(String, String)> = None;
+    let mut raw_html = false;
+    for event in Parser::new_ext(markdown, options) {
+        if let Some((_, alt)) = image.as_mut() {
+            match event {
+                Event::End(TagEnd::Image) => {
+                    let (src, alt) = image.take().unwrap_or_default();
+                    writer.write(
+                        XmlEvent::start_element("img")
+                            .attr("src", &src)
+                            .attr("alt", &alt),
+                    )?;
+                    writer.write(XmlEvent::end_element())?;
+                }
+                Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(tag) => {
+                let elements: Vec<&str> = match tag {
+                    Tag::Paragraph => vec!["p"],
+                    Tag::Heading { level, .. } => vec![heading(level)],
+                    Tag::BlockQuote(_) => vec!["blockquote"],
+                    Tag::CodeBlock(_) => vec!["pre"],
+                    Tag::List(Some(_)) => vec!["ol"],
+                    Tag::List(None) => vec!["ul"],
+                    Tag::Item => vec!["li"],
+                    Tag::Table(_) => vec!["table"],
+                    Tag::TableHead => {
+                        table_head = true;
+                        vec!["thead", "tr"]
+                    }
+                    Tag::TableRow => {
+                        if !table_body {
+                            table_body = true;
+                            writer.write(XmlEvent::start_element("tbody"))?;
+                        }
+                        vec!["tr"]
+                    }
+                    Tag::TableCell if table_head => vec!["th"],
+                    Tag::TableCell => vec!["td"],
+                    Tag::Emphasis => vec!["em"],
+                    Tag::Strong => vec!["strong"],
+                    Tag::Link { dest_url, .. } if is_safe_url(&dest_url) => {
+                        writer.write(XmlEvent::start_element("a").attr("href", &dest_url))?;
+                        open.push(1);
+                        continue;
+                    }
+                    Tag::Image { dest_url, .. } if is_safe_url(&dest_url) => {
+                        image = Some((dest_url.to_string(), String::new()));
+                        continue;
+                    }
+                    // The text of the others is kept without the element
+                    _ => Vec::new(),
+                };
+                for element in &elements {
+                    writer.write(XmlEvent::start_element(*element))?;
+                }
+                open.push(elements.len());
+            }
+            Event::End(tag) => {
+                match tag {
+                    TagEnd::TableHead => table_head = false,
+                    TagEnd::Table if table_body => {
+                        table_body = false;
+                        writer.write(XmlEvent::end_element())?;
+                    }
+                    _ => {}
+                }
+                for _ in 0..open.pop().unwrap_or_default() {
+                    writer.write(XmlEvent::end_element())?;
+                }
+            }
+            Event::Text(text) => writer.write(XmlEvent::characters(&text))?,
+            Event::Code(code) => write_text_element(writer, "code", &code)?,
+            Event::SoftBreak => writer.write(XmlEvent::characters("\n"))?,
+            Event::HardBreak => {
+                writer.write(XmlEvent::start_element("br"))?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            Event::Rule => {
+                writer.write(XmlEvent::start_element("hr"))?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            Event::TaskListMarker(checked) => {
+                writer.write(XmlEvent::characters(if checked { "[x] " } else { "[ ] " }))?
+            }
+            Event::Html(_) | Event::InlineHtml(_) => raw_html = true,
+            _ => {}
+        }
+    }
+    if raw_html {
+        tracing::warn!("Raw HTML in the instructions is left out of the QTI item");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown_xml(markdown: &str) -> String {
+        let mut output = Vec::new();
+        let mut writer = EmitterConfig::new()
+            .write_document_declaration(false)
+            .create_writer(&mut output);
+        writer.write(XmlEvent::start_element("itemBody")).unwrap();
+        write_markdown(&mut writer, markdown).unwrap();
+        writer.write(XmlEvent::end_element()).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            markdown_xml("# Task\n\nFind the *flag*  \nin `secret.sh`.\n\n---"),
+            "<itemBody><h1>Task</h1><p>Find the <em>flag</em><br />in <code>secret.sh</code>.</p><hr /></itemBody>"
+        );
+        assert_eq!(
+            markdown_xml("| a | b |\n|---|---|\n| 1 | 2 |"),
+            "<itemBody><table><thead><tr><th>a</th><th>b</th></tr></thead><tbody><tr><td>1</td><td>2</td></tr></tbody></table></itemBody>"
+        );
+        assert_eq!(
+            markdown_xml("![map \"A\" & B](map.png) [files](item-1/files.zip)"),
+            "<itemBody><p><img src=\"map.png\" alt=\"map &quot;A&quot; &amp; B\" /> <a href=\"item-1/files.zip\">files</a></p></itemBody>"
+        );
+    }
+
+    #[test]
+    fn test_markdown_is_sanitized() {
+        let xml = markdown_xml(
+            "<script>alert(1)</script>\n\n[link](javascript:alert(1)) ~~old~~ <b>bold</b>",
+        );
+        assert_eq!(xml, "<itemBody><p>link old bold</p></itemBody>");
+    }
+
+    #[test]
+    fn test_mapped_answers() {
+        let answers = [
+            Answer::new(50, "a".to_string(), None),
+            Answer::new(100, "a b".to_string(), None),
+            Answer::new(100, "a".to_string(), None),
+            Answer::new(0, "*".to_string(), None),
+        ];
+        assert_eq!(mapped_answers(&answers), [("a", 100), ("a b", 100)]);
+    }
+}
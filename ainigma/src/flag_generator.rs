@@ -188,19 +188,56 @@ fn user_derived_flag(
     secret: &str,
     taskid: &str,
 ) -> Result<String, InvalidLength> {
+    let result = user_mac(algorithm, uuid, secret, taskid)?.finalize();
+    let bytes = result.into_bytes();
+    Ok(format!("{bytes:x}"))
+}
+
+/// The MAC of the task for the user, which is the suffix of the user derived flag
+fn user_mac(
+    algorithm: &Algorithm,
+    uuid: &Uuid,
+    secret: &str,
+    taskid: &str,
+) -> Result<Hmac256, InvalidLength> {
     match algorithm {
         Algorithm::HMAC_SHA3_256 => {
             let input = format!("{}-{}", secret, uuid.as_hyphenated());
             let slice = input.as_bytes();
             let mut mac = Hmac256::new_from_slice(slice)?;
             mac.update(taskid.as_bytes());
-
-            let result = mac.finalize();
-            let bytes = result.into_bytes();
-            Ok(format!("{bytes:x}"))
+            Ok(mac)
         }
     }
 }
+/// Checks that the suffix is the user derived flag of the given user, task and secret
+///
+/// The suffix is compared in constant time, so the time taken does not reveal how much of it is correct.
+pub fn verify_user_flag(
+    algorithm: &Algorithm,
+    uuid: &Uuid,
+    secret: &str,
+    taskid: &str,
+    suffix: &str,
+) -> bool {
+    let digits = suffix.trim().as_bytes();
+    let Some(bytes) = digits
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => {
+                let high = char::from(*high).to_digit(16)?;
+                let low = char::from(*low).to_digit(16)?;
+                u8::try_from(high << 4 | low).ok()
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()
+    else {
+        return false;
+    };
+    user_mac(algorithm, uuid, secret, taskid).is_ok_and(|mac| mac.verify_slice(&bytes).is_ok())
+}
+
 // not used might be used later
 #[allow(dead_code)]
 fn compare_hmac(
@@ -243,6 +280,55 @@ mod tests {
         assert!(compare_hmac(hash, id, secret2, taskid2).expect("should work"))
     }
 
+    #[test]
+    fn test_verify_user_flag() {
+        let id = Uuid::now_v7();
+        let flag = Flag::new_user_flag(
+            "task1".to_string(),
+            &Algorithm::HMAC_SHA3_256,
+            "old secret",
+            "task1",
+            &id,
+        );
+        let Flag::UserDerivedFlag(unit) = &flag else {
+            unreachable!()
+        };
+        assert!(verify_user_flag(
+            &Algorithm::HMAC_SHA3_256,
+            &id,
+            "old secret",
+            "task1",
+            unit.value()
+        ));
+        assert!(!verify_user_flag(
+            &Algorithm::HMAC_SHA3_256,
+            &id,
+            "new secret",
+            "task1",
+            unit.value()
+        ));
+        assert!(!verify_user_flag(
+            &Algorithm::HMAC_SHA3_256,
+            &Uuid::now_v7(),
+            "old secret",
+            "task1",
+            unit.value()
+        ));
+        let verify = |suffix: &str| {
+            verify_user_flag(
+                &Algorithm::HMAC_SHA3_256,
+                &id,
+                "old secret",
+                "task1",
+                suffix,
+            )
+        };
+        assert!(verify(&format!(" {} ", unit.value().to_uppercase())));
+        assert!(!verify(&unit.value()[2..]));
+        assert!(!verify(&format!("{}0", unit.value())));
+        assert!(!verify(&format!("+{}", &unit.value()[1..])));
+    }
+
     #[test]
     fn test_outputs() {
         let id = Uuid::now_v7();
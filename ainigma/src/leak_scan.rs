@@ -0,0 +1,345 @@
+//! Scanning the files given to the students for the flags of the variant
+//!
+//! Resource files and the readme are searched for the encased flag and the flag suffix.
+//! Besides the plain bytes, the flags are searched as UTF-16 and base64, and inside zip, tar and gzip archives.
+//! Suffixes shorter than [`MIN_NEEDLE_LENGTH`] are not searched, as they would match by accident.
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use crate::build_process::{IntermediateOutput, TaskBuildContainer};
+use crate::config::LeakCheck;
+use crate::errors::BuildError;
+use crate::flag_generator::Flag;
+
+pub const MIN_NEEDLE_LENGTH: usize = 8;
+/// Archives inside archives are opened up to this depth
+const MAX_ARCHIVE_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Plain,
+    Utf16Le,
+    Utf16Be,
+    Base64,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Plain => write!(f, "plain text"),
+            Encoding::Utf16Le => write!(f, "UTF-16LE"),
+            Encoding::Utf16Be => write!(f, "UTF-16BE"),
+            Encoding::Base64 => write!(f, "base64"),
+        }
+    }
+}
+
+/// Flag found in a file given to the students
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    /// File name, followed by the paths inside archives separated with `:`
+    pub location: String,
+    /// Identifier of the leaked flag
+    pub flag: String,
+    pub encoding: Encoding,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "flag {} in '{}' as {}",
+            self.flag, self.location, self.encoding
+        )
+    }
+}
+
+struct Needle {
+    flag: String,
+    encoding: Encoding,
+    bytes: Vec<u8>,
+}
+
+/// Scan the variants of the build and apply the leak check policy of the task
+pub fn check_leaks(build: &TaskBuildContainer) -> Result<(), BuildError> {
+    let policy = build.task.build.leak_check;
+    if policy == LeakCheck::Off {
+        return Ok(());
+    }
+    let mut leaks = Vec::new();
+    for instance in &build.outputs {
+        for leak in scan_instance(instance)? {
+            tracing::warn!(
+                "Task {} variant {} leaks the {}",
+                build.task.id,
+                instance.uuid,
+                leak
+            );
+            leaks.push(leak);
+        }
+    }
+    if policy == LeakCheck::Fail && !leaks.is_empty() {
+        return Err(BuildError::FlagLeak(format!(
+            "task {} has {} leaks, first one is the {}",
+            build.task.id,
+            leaks.len(),
+            leaks[0]
+        )));
+    }
+    Ok(())
+}
+
+/// Search the resource files and the readme of a variant for its flags
+pub fn scan_instance(instance: &IntermediateOutput) -> Result<Vec<Leak>, BuildError> {
+    let needles = needles(&instance.stage_flags);
+    let files = instance
+        .get_resource_files()
+        .into_iter()
+        .chain(instance.get_readme().cloned());
+    let mut leaks = Vec::new();
+    for item in files {
+        let path = instance.task_instance_dir.join(item.kind.get_filename());
+        let data = std::fs::read(&path).map_err(|e| {
+            BuildError::OutputVerificationFailed(format!(
+                "Cannot read '{}' for the leak check: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        scan_bytes(&name, &data, &needles, 0, &mut leaks);
+    }
+    Ok(leaks)
+}
+
+fn needles(flags: &[Flag]) -> Vec<Needle> {
+    let mut needles = Vec::new();
+    for flag in flags {
+        let flag_string = flag.flag_string();
+        let suffix = flag_string
+            .split_once(':')
+            .map_or(flag_string.as_str(), |(_, suffix)| suffix);
+        for value in [flag.encased(), suffix] {
+            if value.len() < MIN_NEEDLE_LENGTH {
+                continue;
+            }
+            let mut add = |encoding, bytes| {
+                needles.push(Needle {
+                    flag: flag.get_identifier().to_string(),
+                    encoding,
+                    bytes,
+                })
+            };
+            add(Encoding::Plain, value.as_bytes().to_vec());
+            add(
+                Encoding::Utf16Le,
+                value.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            );
+            add(
+                Encoding::Utf16Be,
+                value.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            );
+            for encoded in base64_variants(value.as_bytes()) {
+                add(Encoding::Base64, encoded.into_bytes());
+            }
+        }
+    }
+    needles
+}
+
+/// Base64 of the value depends on its offset in the encoded data.
+/// For each of the three offsets, only the characters which depend on the value alone are kept.
+fn base64_variants(value: &[u8]) -> Vec<String> {
+    (0..3)
+        .filter_map(|offset| {
+            let mut data = vec![0u8; offset];
+            data.extend_from_slice(value);
+            let encoded = STANDARD.encode(&data);
+            let start = if offset > 0 { 4 } else { 0 };
+            let end = data.len() / 3 * 4;
+            (end > start && end - start >= MIN_NEEDLE_LENGTH)
+                .then(|| encoded[start..end].to_string())
+        })
+        .collect()
+}
+
+fn scan_bytes(
+    location: &str,
+    data: &[u8],
+    needles: &[Needle],
+    depth: usize,
+    leaks: &mut Vec<Leak>,
+) {
+    for needle in needles {
+        let leak = Leak {
+            location: location.to_string(),
+            flag: needle.flag.clone(),
+            encoding: needle.encoding,
+        };
+        if !leaks.contains(&leak) && contains(data, &needle.bytes) {
+            leaks.push(leak);
+        }
+    }
+    if depth >= MAX_ARCHIVE_DEPTH {
+        return;
+    }
+    // Files which only look like archives are scanned as they are
+    if data.starts_with(b"PK\x03\x04") {
+        let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(data)) else {
+            return;
+        };
+        for index in 0..archive.len() {
+            let Ok(mut file) = archive.by_index(index) else {
+                continue;
+            };
+            let mut content = Vec::new();
+            if file.is_file() && file.read_to_end(&mut content).is_ok() {
+                let location = format!("{location}:{}", file.name());
+                scan_bytes(&location, &content, needles, depth + 1, leaks);
+            }
+        }
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        let mut content = Vec::new();
+        if flate2::read::GzDecoder::new(data)
+            .read_to_end(&mut content)
+            .is_ok()
+        {
+            scan_bytes(location, &content, needles, depth + 1, leaks);
+        }
+    } else if data.get(257..262) == Some(b"ustar") {
+        let mut archive = tar::Archive::new(data);
+        let Ok(entries) = archive.entries() else {
+            return;
+        };
+        for mut entry in entries.flatten() {
+            let mut content = Vec::new();
+            if entry.header().entry_type().is_file() && entry.read_to_end(&mut content).is_ok() {
+                let name = entry
+                    .path()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let location = format!("{location}:{name}");
+                scan_bytes(&location, &content, needles, depth + 1, leaks);
+            }
+        }
+    }
+}
+
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    !needle.is_empty() && data.windows(needle.len()).any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn flag() -> Flag {
+        Flag::new_random_flag("task001".to_string(), 16)
+    }
+
+    fn scan(flag: &Flag, data: &[u8]) -> Vec<Leak> {
+        let mut leaks = Vec::new();
+        scan_bytes(
+            "file",
+            data,
+            &needles(std::slice::from_ref(flag)),
+            0,
+            &mut leaks,
+        );
+        leaks
+    }
+
+    fn suffix(flag: &Flag) -> String {
+        flag.flag_string().split_once(':').unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_plain_and_utf16() {
+        let flag = flag();
+        assert!(scan(&flag, b"nothing to see here").is_empty());
+
+        let leaks = scan(&flag, format!("echo {}", flag.encased()).as_bytes());
+        assert!(
+            leaks
+                .iter()
+                .all(|leak| leak.encoding == Encoding::Plain && leak.flag == "task001")
+        );
+        assert!(!leaks.is_empty());
+
+        let utf16: Vec<u8> = suffix(&flag)
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let leaks = scan(&flag, &utf16);
+        assert_eq!(leaks[0].encoding, Encoding::Utf16Le);
+    }
+
+    #[test]
+    fn test_base64_offsets() {
+        let flag = flag();
+        for prefix in ["", "a", "ab", "abc"] {
+            let encoded = STANDARD.encode(format!("{prefix}{}!", suffix(&flag)));
+            let leaks = scan(&flag, encoded.as_bytes());
+            assert!(
+                leaks.iter().any(|leak| leak.encoding == Encoding::Base64),
+                "Base64 with prefix '{prefix}' was not found"
+            );
+        }
+    }
+
+    #[test]
+    fn test_archives() {
+        let flag = flag();
+        let mut zip_data = Vec::new();
+        {
+            let mut writer = zip::ZipWriter::new(Cursor::new(&mut zip_data));
+            writer
+                .start_file("inner/notes.txt", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(flag.encased().as_bytes()).unwrap();
+            writer.finish().unwrap();
+        }
+        let leaks = scan(&flag, &zip_data);
+        assert!(
+            leaks
+                .iter()
+                .any(|leak| leak.location == "file:inner/notes.txt")
+        );
+
+        // Zip inside a gzipped tar
+        let mut tar_data = Vec::new();
+        {
+            let mut builder = tar::Builder::new(&mut tar_data);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(zip_data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "files.zip", zip_data.as_slice())
+                .unwrap();
+            builder.finish().unwrap();
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&tar_data).unwrap();
+        let tar_gz = encoder.finish().unwrap();
+        let leaks = scan(&flag, &tar_gz);
+        assert!(
+            leaks
+                .iter()
+                .any(|leak| leak.location == "file:files.zip:inner/notes.txt")
+        );
+    }
+
+    #[test]
+    fn test_short_suffix_is_skipped() {
+        let flag = Flag::new_random_flag("task001".to_string(), 2);
+        // Only the encased form is long enough to be searched
+        assert_eq!(needles(std::slice::from_ref(&flag)).len(), 6);
+    }
+}
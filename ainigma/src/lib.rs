@@ -1,8 +1,20 @@
 pub mod build_process;
+pub mod cache;
+pub mod checksum;
 pub mod config;
+pub mod container;
+pub mod events;
+pub mod export;
 pub mod flag_generator;
+pub mod leak_scan;
+pub mod markdown;
 pub mod moodle;
+pub mod package;
+pub mod reproducible;
+pub mod sandbox;
 
 pub mod storages;
+pub mod template;
+pub mod verify;
 
 pub mod errors;
@@ -0,0 +1,77 @@
+//! Rendering Markdown instructions to sanitized HTML
+//!
+//! The same renderer is used for the Moodle questions and by the server, so the instructions look the same everywhere.
+//! Raw HTML in the Markdown is sanitized, and fenced code blocks keep their `language-*` class for syntax highlighters.
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+use pulldown_cmark::{Options, Parser, html};
+
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tag_attributes("code", ["class"])
+        .attribute_filter(|element, attribute, value| {
+            if element == "code" && attribute == "class" {
+                let classes: Vec<&str> = value
+                    .split_whitespace()
+                    .filter(|class| is_language_class(class))
+                    .collect();
+                return (!classes.is_empty()).then(|| Cow::Owned(classes.join(" ")));
+            }
+            Some(Cow::Borrowed(value))
+        });
+    builder
+});
+
+/// Render the Markdown text to HTML which is safe to show to the students
+pub fn to_html(markdown: &str) -> String {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+/// Class of a fenced code block, e.g. `language-rust`
+fn is_language_class(class: &str) -> bool {
+    class.strip_prefix("language-").is_some_and(|language| {
+        !language.is_empty()
+            && language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '#'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_html() {
+        assert_eq!(
+            to_html("# Task\n\nFind the **flag**."),
+            "<h1>Task</h1>\n<p>Find the <strong>flag</strong>.</p>\n"
+        );
+        assert_eq!(
+            to_html("```python\nprint(1)\n```"),
+            "<pre><code class=\"language-python\">print(1)\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_sanitization() {
+        let html = to_html(
+            "<script>alert(1)</script>\n\n<a href=\"javascript:alert(1)\" onclick=\"x()\">link</a>",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("javascript"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains(">link</a>"));
+        // Only the language classes are kept
+        let html = to_html("<code class=\"language-c evil\">x</code>");
+        assert!(html.contains("<code class=\"language-c\">"));
+        let html = to_html("<code class=\"evil\">x</code>");
+        assert!(html.contains("<code>x</code>"));
+    }
+}
@@ -1,107 +1,432 @@
-use crate::build_process::TaskBuildContainer;
-use crate::config::OutputKind;
+use crate::build_process::{Challenge, IntermediateOutput, OutputItem, TaskBuildContainer};
+use crate::config::{ModuleConfiguration, MoodleConfig, OutputKind, Task};
+use crate::errors::BuildError;
+use crate::export::{Download, ExportFormat, VariantQuestion};
 use crate::flag_generator::Flag;
+use crate::package::entry_name;
+use crate::{markdown, template};
 use itertools::Itertools;
-use moodle_xml::{
-    answer::Answer,
-    question::{Question, QuestionType, ShortAnswerQuestion},
-    quiz::Quiz,
-};
-use std::io::{self, BufRead, BufReader};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+pub mod quiz;
+pub mod responses;
+
+use quiz::{Answer, EmbeddedFile, ShortAnswerQuestion};
+
+/// Options of the generated Moodle exam
+#[derive(Debug, Clone, Default)]
+pub struct ExamOptions {
+    /// Category of the questions, nested with `/`, see [`nested_category`]
+    pub category: String,
+    /// Format of the exam file
+    pub format: ExportFormat,
+    /// Embed the files which were not uploaded into the questions, if they are at most this many bytes
+    pub embed_limit: Option<u64>,
+    /// How the answers of the questions match the flags
+    pub answer_matching: AnswerMatching,
+}
+
+/// How the answers of the questions match the flags of the stages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnswerMatching {
+    /// An answer for each ordering of each subset of the flags, separated by a space
+    #[default]
+    Permutations,
+    /// A regexp question for each variant, which accepts the flags in any order with any separators and gives
+    /// partial credit for each found flag
+    Regexp,
+    /// A regexp question for each stage of each variant
+    RegexpStages,
+}
+
+impl fmt::Display for AnswerMatching {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AnswerMatching::Permutations => "permutations",
+            AnswerMatching::Regexp => "regexp",
+            AnswerMatching::RegexpStages => "regexp-stages",
+        })
+    }
+}
+
+impl FromStr for AnswerMatching {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "permutations" => Ok(AnswerMatching::Permutations),
+            "regexp" => Ok(AnswerMatching::Regexp),
+            "regexp-stages" => Ok(AnswerMatching::RegexpStages),
+            other => Err(format!(
+                "unknown answer matching '{other}', expected permutations, regexp or regexp-stages"
+            )),
+        }
+    }
+}
 
 /// Create an exam from a list of task build process outputs, which includes the question as well
 pub fn create_exam(
     items: TaskBuildContainer,
-    category: &str,
+    options: &ExamOptions,
     filename: &str,
-    disable_upload: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut questions: Vec<QuestionType> = Vec::with_capacity(items.outputs.len());
+    let exporter = options.format.exporter();
+    if options.embed_limit.is_some() && !exporter.supports_files() {
+        return Err(io::Error::other(format!(
+            "Files cannot be embedded into the {} format",
+            options.format
+        ))
+        .into());
+    }
+    // The regexp question type is a Moodle plugin
+    if options.answer_matching != AnswerMatching::Permutations
+        && options.format != ExportFormat::MoodleXml
+    {
+        return Err(io::Error::other(format!(
+            "The {} answer matching is only supported in the {} format",
+            options.answer_matching,
+            ExportFormat::MoodleXml
+        ))
+        .into());
+    }
+    let embed_limit = options.embed_limit.or(options.format.bundled_file_limit());
+    let mut questions: Vec<VariantQuestion> = Vec::with_capacity(items.outputs.len());
 
-    for item in items.outputs {
+    // A template places the links and the meta values itself
+    let templated = items.task.build.readme_template.is_some();
+    for item in &items.outputs {
         // TODO batch not supported yet
-        let instructions = item.get_readme();
-        match instructions {
-            Some(instructions) => {
-                let file = std::fs::File::open(instructions.kind.get_filename()).unwrap();
-                let reader = BufReader::new(file);
-                let mut instructions: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
-                instructions.push("".to_string());
-
-                if !disable_upload {
-                    instructions.push("<br><br><b>Please, see the download links below. Exam questions are randomised and the links are different if you retry the exam.</b>".to_string());
-                    instructions.push("<br>".to_string());
-                    instructions.push(
-                    "<div style=\"display: flex; flex-wrap: wrap; gap: 10px; margin-top: 10px;\">"
-                        .to_string(),
-                );
-                    for link in &item.outputs {
-                        if let (OutputKind::Resource(resource), Some(link)) =
-                            (&link.kind, &link.link)
-                        {
-                            instructions.push(format!(
-                                "<a href=\"{}\" target=\"_blank\" class=\"btn btn-primary\">{}</a>",
-                                link,
-                                resource
+        // Embedded files get links inside the question, which the template can use too
+        let mut item = item.clone();
+        let files = match embed_limit {
+            Some(limit) => embed_files(&mut item, limit)?,
+            None => Vec::new(),
+        };
+        match template::instructions(&items.task, &items.task.build.directory, &item) {
+            Ok(instructions) => {
+                let meta = item.meta.clone().filter(|_| !templated);
+                // A package replaces the separate resource files
+                let downloads: Vec<Download> = if templated {
+                    Vec::new()
+                } else {
+                    item.get_distributed_files()
+                        .into_iter()
+                        .filter_map(|file| match (&file.kind, file.link) {
+                            (OutputKind::Resource(resource), Some(link)) => Some(Download {
+                                name: resource
                                     .file_name()
                                     .unwrap_or_default()
                                     .to_ascii_lowercase()
-                                    .to_string_lossy(),
-                            ));
-                        }
+                                    .to_string_lossy()
+                                    .into_owned(),
+                                link,
+                            }),
+                            _ => None,
+                        })
+                        .collect()
+                };
+
+                let mut text = vec![markdown::to_html(&instructions)];
+                if let Some(meta) = &meta {
+                    text.extend(meta_html(meta));
+                }
+                if !downloads.is_empty() {
+                    text.push("<br><br><b>Please, see the download links below. Exam questions are randomised and the links are different if you retry the exam.</b>".to_string());
+                    text.push("<br>".to_string());
+                    text.push(
+                        "<div style=\"display: flex; flex-wrap: wrap; gap: 10px; margin-top: 10px;\">"
+                            .to_string(),
+                    );
+                    for download in &downloads {
+                        text.push(format!(
+                            "<a href=\"{}\" target=\"_blank\" class=\"btn btn-primary\">{}</a>",
+                            escape_html(&download.link),
+                            escape_html(&download.name),
+                        ));
                     }
-                    instructions.push("</div>".to_string());
+                    text.push("</div>".to_string());
                 }
 
-                let instructions_string = instructions.join("\n");
                 let mut question =
-                    ShortAnswerQuestion::new(items.task.name.clone(), instructions_string, None);
-                let answers = if item.stage_flags.len() == 1 {
-                    // Unknown flag, task build process has created this one
-                    if let Flag::RngSeed(flag) = &item.stage_flags[0] {
-                        vec![Answer::new(
-                            100,
-                            flag.value().to_string(),
-                            "Correct!".to_string().into(),
-                        )]
-                    } else {
-                        vec![
-                            Answer::new(
-                                100,
-                                item.stage_flags[0].encased().to_string(),
-                                "Correct!".to_string().into(),
-                            ),
-                            Answer::new(
-                                100,
-                                item.stage_flags[0].flag_string(),
-                                "Correct!".to_string().into(),
-                            ),
-                        ]
+                    ShortAnswerQuestion::new(items.task.name.clone(), text.join("\n"));
+                question.files = files;
+                let variables = template::variables(&items.task, &item);
+                let feedback = apply_metadata(&mut question, &items.task.moodle, &variables)?;
+                let variant_question = |mut question: ShortAnswerQuestion| {
+                    feedback.apply(&mut question);
+                    VariantQuestion {
+                        uuid: item.uuid,
+                        instructions: instructions.clone(),
+                        meta: meta.clone(),
+                        downloads: downloads.clone(),
+                        question,
                     }
-                } else {
-                    // Adds 1-inf flags as answer with chosen separator
-                    process_multiple_flags(item.stage_flags.clone(), " ")
                 };
-                question
-                    .add_answers(answers)
-                    .map_err(|e| io::Error::other(format!("Error: {e:?}")))?;
-                questions.push(question.into());
+                match options.answer_matching {
+                    AnswerMatching::Permutations => {
+                        question.answers = flag_answers(&item.stage_flags);
+                        questions.push(variant_question(question));
+                    }
+                    AnswerMatching::Regexp => {
+                        question.regexp = true;
+                        question.answers = regexp_answers(&item.stage_flags);
+                        questions.push(variant_question(question));
+                    }
+                    AnswerMatching::RegexpStages => {
+                        for (i, flag) in item.stage_flags.iter().enumerate() {
+                            let mut stage_question = question.clone();
+                            if item.stage_flags.len() > 1 {
+                                let label = items
+                                    .task
+                                    .stages
+                                    .get(i)
+                                    .and_then(|stage| stage.name.as_ref().or(stage.id.as_ref()))
+                                    .cloned()
+                                    .unwrap_or_else(|| format!("stage {}", i + 1));
+                                stage_question.name = format!("{} ({})", question.name, label);
+                            }
+                            stage_question.regexp = true;
+                            stage_question.answers = regexp_answers(std::slice::from_ref(flag));
+                            questions.push(variant_question(stage_question));
+                        }
+                    }
+                }
             }
-            None => {
-                panic!(
-                    "No instructions provided for Moodle exam for unkown reason. Verify that you have `readme` type in output files."
+            Err(error) => {
+                tracing::error!(
+                    "No instructions for the Moodle exam. Verify that you have `readme` type in output files or a readme template."
                 );
+                return Err(error.into());
             }
         }
     }
-    let mut quiz = Quiz::new(questions);
-    let categories = vec![category.into()];
-    quiz.set_categories(categories);
-    quiz.to_xml(filename)
-        .map_err(|e| io::Error::other(format!("Error: {e:?}")))?;
+    if questions.is_empty() {
+        return Err(io::Error::other("The exam has no questions").into());
+    }
+    let mut output = io::BufWriter::new(std::fs::File::create(filename)?);
+    exporter.write(&options.category, &questions, &mut output)?;
+    output.flush()?;
     Ok(())
 }
 
+/// Category of the questions of the task which mirrors the configuration: `<module name>/<category name>/<task name>`
+///
+/// Each task gets its own category, which suits the random question slots of Moodle quizzes.
+pub fn nested_category(config: &ModuleConfiguration, task: &Task) -> Option<String> {
+    let category = config.get_category_by_task_id(&task.id)?;
+    Some(
+        [&config.name, &category.name, &task.name]
+            .into_iter()
+            .map(|name| escape_category(name))
+            .join("/"),
+    )
+}
+
+/// Moodle separates the nested categories with `/`, so a `/` in a name is doubled
+fn escape_category(name: &str) -> String {
+    name.trim().replace('/', "//")
+}
+
+/// Rendered answer feedback of the questions of a variant
+struct Feedback {
+    correct: String,
+    incorrect: Option<String>,
+}
+
+impl Feedback {
+    /// Set the feedback of the flag answers, and add an answer for anything else if there is feedback for it
+    fn apply(&self, question: &mut ShortAnswerQuestion) {
+        for answer in &mut question.answers {
+            answer.feedback = Some(self.correct.clone());
+        }
+        if let Some(incorrect) = &self.incorrect {
+            let anything = if question.regexp { ".*" } else { "*" };
+            question.answers.push(Answer::new(
+                0,
+                anything.to_string(),
+                Some(incorrect.clone()),
+            ));
+        }
+    }
+}
+
+/// Set the name, general feedback, penalty, hints and tags of the task to the question, and render the feedback of the
+/// answers
+fn apply_metadata(
+    question: &mut ShortAnswerQuestion,
+    moodle: &MoodleConfig,
+    variables: &BTreeMap<String, String>,
+) -> Result<Feedback, BuildError> {
+    let render = |text: &str| template::render(text, variables);
+    if let Some(name) = &moodle.name {
+        question.name = render(name)?;
+    }
+    question.general_feedback = moodle.general_feedback.as_deref().map(render).transpose()?;
+    question.penalty = moodle.penalty;
+    question.hints = moodle
+        .hints
+        .iter()
+        .map(|hint| render(hint))
+        .collect::<Result<_, _>>()?;
+    question.tags = moodle.tags.clone();
+    Ok(Feedback {
+        correct: render(&moodle.correct_feedback)?,
+        incorrect: moodle
+            .incorrect_feedback
+            .as_deref()
+            .map(render)
+            .transpose()?,
+    })
+}
+
+/// Accepted answers for the flags of the variant
+pub fn flag_answers(stage_flags: &[Flag]) -> Vec<Answer> {
+    if stage_flags.len() == 1 {
+        // Unknown flag, task build process has created this one
+        if let Flag::RngSeed(flag) = &stage_flags[0] {
+            vec![Answer::new(
+                100,
+                flag.value().to_string(),
+                "Correct!".to_string().into(),
+            )]
+        } else {
+            vec![
+                Answer::new(
+                    100,
+                    stage_flags[0].encased().to_string(),
+                    "Correct!".to_string().into(),
+                ),
+                Answer::new(
+                    100,
+                    stage_flags[0].flag_string(),
+                    "Correct!".to_string().into(),
+                ),
+            ]
+        }
+    } else {
+        // Adds 1-inf flags as answer with chosen separator
+        process_multiple_flags(stage_flags.to_vec(), " ")
+    }
+}
+
+/// Answers of a regexp question for the flags of the variant
+///
+/// The first answer is the flags separated by spaces, which Moodle shows as the correct answer. It is followed by a
+/// regular expression for each count of found flags, from all of them down to one, so the number of answers grows
+/// linearly with the stages. The flags are accepted with or without their prefix and braces, in any order and with any
+/// separators, and a flag given twice counts only once.
+pub fn regexp_answers(stage_flags: &[Flag]) -> Vec<Answer> {
+    let mut answers = vec![Answer::new(
+        100,
+        encase_each_flag(stage_flags, " "),
+        "Correct!".to_string().into(),
+    )];
+    let alternatives = stage_flags
+        .iter()
+        .map(|flag| match flag {
+            // Unknown flag, task build process has created this one
+            Flag::RngSeed(flag) => escape_regexp(flag.value()),
+            flag => escape_regexp(&flag.flag_string()),
+        })
+        .join("|");
+    let total_flags = stage_flags.len();
+    for found in (1..=total_flags).rev() {
+        let points = ((found as f64 / total_flags as f64) * 100.0).round() as i8;
+        // A flag matches only at its last occurrence, so each match is a different flag
+        answers.push(Answer::new(
+            points,
+            format!("^(?:.*?({alternatives})(?!.*\\1)){{{found}}}.*$"),
+            "Correct!".to_string().into(),
+        ));
+    }
+    answers
+}
+
+/// Escape the special characters of PCRE, which Moodle uses, and the `/` delimiter
+fn escape_regexp(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$/#-".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Embed the files of the variant which have no link and fit into the limit, and link them to the embedded copies
+fn embed_files(instance: &mut IntermediateOutput, limit: u64) -> io::Result<Vec<EmbeddedFile>> {
+    let instance_dir = instance.task_instance_dir.clone();
+    let uuid = instance.uuid;
+    let items: Vec<&mut OutputItem> = match &mut instance.package {
+        Some(package) => vec![&mut package.item],
+        None => instance
+            .outputs
+            .iter_mut()
+            .filter(|item| matches!(item.kind, OutputKind::Resource(_)))
+            .collect(),
+    };
+    let mut files = Vec::new();
+    for item in items.into_iter().filter(|item| item.link.is_none()) {
+        let path = instance_dir.join(item.kind.get_filename());
+        let size = match item.size {
+            Some(size) => size,
+            None => std::fs::metadata(&path)?.len(),
+        };
+        if size > limit {
+            tracing::warn!(
+                "'{}' of variant {} has {} bytes, which is over the embedding limit of {} bytes. It is not in the question.",
+                path.display(),
+                uuid,
+                size,
+                limit
+            );
+            continue;
+        }
+        let file = EmbeddedFile::read(&path, &entry_name(&instance_dir, &path))?;
+        item.set_link(file.link());
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// The address and other details of the variant reported by the builder
+fn meta_html(meta: &Challenge) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(url) = &meta.url {
+        let url = escape_html(url);
+        lines.push(format!(
+            "<p>Address of your challenge: <a href=\"{url}\" target=\"_blank\">{url}</a></p>"
+        ));
+    }
+    if !meta.metadata.is_empty() {
+        lines.push("<ul>".to_string());
+        for (key, value) in &meta.metadata {
+            let value = match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            lines.push(format!(
+                "<li><b>{}</b>: {}</li>",
+                escape_html(key),
+                escape_html(&value)
+            ));
+        }
+        lines.push("</ul>".to_string());
+    }
+    lines
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 // Function to encase each flag with a specified separator
 fn encase_each_flag(flags: &[Flag], separator: &str) -> String {
     flags
@@ -159,6 +484,30 @@ mod tests {
     use crate::flag_generator::Algorithm;
     use uuid::Uuid;
 
+    #[test]
+    fn test_meta_html() {
+        let meta = Challenge {
+            uuid: Uuid::nil(),
+            flag: None,
+            url: Some("https://example.com/?a=1&b=2".to_string()),
+            metadata: [
+                ("port".to_string(), serde_json::json!(8080)),
+                ("user".to_string(), serde_json::json!("<student>")),
+            ]
+            .into(),
+        };
+        assert_eq!(
+            meta_html(&meta),
+            [
+                "<p>Address of your challenge: <a href=\"https://example.com/?a=1&amp;b=2\" target=\"_blank\">https://example.com/?a=1&amp;b=2</a></p>",
+                "<ul>",
+                "<li><b>port</b>: 8080</li>",
+                "<li><b>user</b>: &lt;student&gt;</li>",
+                "</ul>",
+            ]
+        );
+    }
+
     #[test]
     fn test_multiple_flags() {
         let mut flags = Vec::new();
@@ -250,4 +599,61 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_regexp_answers() {
+        let flags = vec![
+            Flag::new_random_flag("task1".to_string(), 8),
+            Flag::new_random_flag("task2".to_string(), 8),
+        ];
+        let answers = regexp_answers(&flags);
+        assert_eq!(
+            answers
+                .iter()
+                .map(|answer| answer.fraction)
+                .collect::<Vec<_>>(),
+            [100, 100, 50]
+        );
+        assert_eq!(
+            answers[0].text,
+            format!("{} {}", flags[0].encased(), flags[1].encased())
+        );
+        let alternatives = format!("{}|{}", flags[0].flag_string(), flags[1].flag_string());
+        assert_eq!(
+            answers[1].text,
+            format!("^(?:.*?({alternatives})(?!.*\\1)){{2}}.*$")
+        );
+        assert_eq!(
+            answers[2].text,
+            format!("^(?:.*?({alternatives})(?!.*\\1)){{1}}.*$")
+        );
+        assert_eq!(escape_regexp("a.b/c{d}"), "a\\.b\\/c\\{d\\}");
+    }
+
+    #[test]
+    fn test_answer_matching_names() {
+        for matching in [
+            AnswerMatching::Permutations,
+            AnswerMatching::Regexp,
+            AnswerMatching::RegexpStages,
+        ] {
+            assert_eq!(matching.to_string().parse(), Ok(matching));
+        }
+        assert!("regex".parse::<AnswerMatching>().is_err());
+    }
+
+    #[test]
+    fn test_nested_category() {
+        let default = include_str!("../tests/data/configs/default.toml");
+        let mut config: ModuleConfiguration = toml::from_str(default).unwrap();
+        config.categories[0].name = "Week 1/2".to_string();
+        let task = config.categories[0].tasks[0].clone();
+        assert_eq!(
+            nested_category(&config, &task),
+            Some(format!("{}/Week 1//2/{}", config.name, task.name))
+        );
+        let mut unknown = task.clone();
+        unknown.id = "unknown".to_string();
+        assert_eq!(nested_category(&config, &unknown), None);
+    }
 }
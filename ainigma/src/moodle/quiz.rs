@@ -0,0 +1,401 @@
+//! Questions of the exams and their Moodle XML form
+//!
+//! The quiz is written with the `moodle-xml` crate. The parts of the short answer questions which it does not
+//! support yet are added to its output: files embedded into the question text, the regexp question type, general
+//! feedback, penalty, hints and tags. See <https://docs.moodle.org/en/Moodle_XML_format>.
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use moodle_xml::prelude::{Question, QuestionType, QuizError};
+use xml::reader::{EventReader, XmlEvent as ReaderEvent};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+/// Prefix of the links to the files embedded into the question
+pub const PLUGINFILE: &str = "@@PLUGINFILE@@";
+
+/// Accepted answer of a question
+#[derive(Debug, Clone)]
+pub struct Answer {
+    /// Percentage of the points given for the answer
+    pub fraction: i8,
+    pub text: String,
+    pub feedback: Option<String>,
+}
+
+impl Answer {
+    pub fn new(fraction: i8, text: String, feedback: Option<String>) -> Self {
+        Self {
+            fraction,
+            text,
+            feedback,
+        }
+    }
+}
+
+/// File stored inside the question, linked from the question text with [`EmbeddedFile::link`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedFile {
+    pub name: String,
+    /// Directory of the file inside the question, starting and ending with `/`
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+impl EmbeddedFile {
+    /// Embed the file at `source` as `relative_path`, e.g. `files/data.txt`
+    pub fn read(source: &Path, relative_path: &str) -> io::Result<Self> {
+        let (directory, name) = match relative_path.rsplit_once('/') {
+            Some((directory, name)) => (format!("/{directory}/"), name),
+            None => ("/".to_string(), relative_path),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            path: directory,
+            contents: std::fs::read(source)?,
+        })
+    }
+    /// Link to the file in the question text
+    pub fn link(&self) -> String {
+        format!("{PLUGINFILE}/{}", self.relative_link())
+    }
+    /// Percent-encoded path of the file without the leading `/`, e.g. `files/my%20data.txt`
+    pub fn relative_link(&self) -> String {
+        format!("{}{}", self.path, self.name)
+            .trim_start_matches('/')
+            .split('/')
+            .map(encode_path_segment)
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Question whose answer is typed in by the student, such as a flag
+#[derive(Debug, Clone)]
+pub struct ShortAnswerQuestion {
+    pub name: String,
+    /// Question text as HTML
+    pub text: String,
+    pub files: Vec<EmbeddedFile>,
+    /// Feedback shown whatever the answer was, as HTML
+    pub general_feedback: Option<String>,
+    /// Fraction of the points lost for each incorrect try
+    pub penalty: Option<f32>,
+    pub answers: Vec<Answer>,
+    /// Answers are case sensitive
+    pub use_case: bool,
+    /// Answers after the first one are regular expressions, which needs the regexp question type plugin of Moodle
+    pub regexp: bool,
+    /// Hints of the interactive mode as HTML
+    pub hints: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl ShortAnswerQuestion {
+    pub fn new(name: String, text: String) -> Self {
+        Self {
+            name,
+            text,
+            files: Vec::new(),
+            general_feedback: None,
+            penalty: None,
+            answers: Vec::new(),
+            use_case: false,
+            regexp: false,
+            hints: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// The question as a `moodle-xml` question, without the parts added by [`Quiz::write`]
+    fn to_moodle(&self) -> io::Result<QuestionType> {
+        let mut question = moodle_xml::question::ShortAnswerQuestion::new(
+            self.name.clone(),
+            self.text.clone(),
+            Some(self.use_case),
+        );
+        question
+            .add_answers(
+                self.answers
+                    .iter()
+                    .map(|answer| {
+                        moodle_xml::answer::Answer::new(
+                            answer.fraction,
+                            answer.text.clone(),
+                            answer.feedback.clone(),
+                        )
+                    })
+                    .collect(),
+            )
+            .map_err(quiz_error)?;
+        Ok(QuestionType::ShortAnswer(question))
+    }
+
+    /// Write the embedded files, the end of the question text is next
+    fn write_files<W: Write>(&self, writer: &mut EventWriter<W>) -> xml::writer::Result<()> {
+        for file in &self.files {
+            let contents = STANDARD.encode(&file.contents);
+            writer.write(
+                XmlEvent::start_element("file")
+                    .attr("name", &file.name)
+                    .attr("path", &file.path)
+                    .attr("encoding", "base64"),
+            )?;
+            writer.write(XmlEvent::characters(&contents))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        Ok(())
+    }
+
+    /// Write the general feedback and the penalty, which follow the question text
+    fn write_feedback<W: Write>(&self, writer: &mut EventWriter<W>) -> xml::writer::Result<()> {
+        if let Some(feedback) = &self.general_feedback {
+            writer.write(XmlEvent::start_element("generalfeedback").attr("format", "html"))?;
+            write_text(writer, feedback, true)?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        if let Some(penalty) = self.penalty {
+            writer.write(XmlEvent::start_element("penalty"))?;
+            writer.write(XmlEvent::characters(&penalty.to_string()))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        Ok(())
+    }
+
+    /// Write the options of the regexp type, the hints and the tags, the end of the question is next
+    fn write_trailer<W: Write>(&self, writer: &mut EventWriter<W>) -> xml::writer::Result<()> {
+        if self.regexp {
+            for option in ["usehint", "studentshowalternate"] {
+                writer.write(XmlEvent::start_element(option))?;
+                writer.write(XmlEvent::characters("0"))?;
+                writer.write(XmlEvent::end_element())?;
+            }
+        }
+        for hint in &self.hints {
+            writer.write(XmlEvent::start_element("hint").attr("format", "html"))?;
+            write_text(writer, hint, true)?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        if !self.tags.is_empty() {
+            writer.write(XmlEvent::start_element("tags"))?;
+            for tag in &self.tags {
+                writer.write(XmlEvent::start_element("tag"))?;
+                write_text(writer, tag, false)?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            writer.write(XmlEvent::end_element())?;
+        }
+        Ok(())
+    }
+}
+
+/// Questions of a single category
+#[derive(Debug, Clone, Default)]
+pub struct Quiz {
+    pub category: Option<String>,
+    pub questions: Vec<ShortAnswerQuestion>,
+}
+
+impl Quiz {
+    pub fn new(questions: Vec<ShortAnswerQuestion>) -> Self {
+        Self {
+            category: None,
+            questions,
+        }
+    }
+
+    pub fn write<W: Write>(&self, output: W) -> io::Result<()> {
+        let questions = self
+            .questions
+            .iter()
+            .map(ShortAnswerQuestion::to_moodle)
+            .collect::<io::Result<_>>()?;
+        let mut quiz = moodle_xml::quiz::Quiz::new(questions);
+        if let Some(category) = &self.category {
+            quiz.set_categories(vec![category.as_str().into()]);
+        }
+        // The crate writes only into files
+        let generated = tempfile::NamedTempFile::new()?;
+        let path = generated.path().to_str().ok_or_else(|| {
+            io::Error::other(format!(
+                "Cannot write the quiz into '{}'",
+                generated.path().display()
+            ))
+        })?;
+        quiz.to_xml(path).map_err(quiz_error)?;
+        self.extend(BufReader::new(generated.reopen()?), output)
+    }
+
+    /// Copy the quiz written by `moodle-xml` into the output with the parts of the questions it does not support
+    fn extend<R: Read, W: Write>(&self, generated: R, output: W) -> io::Result<()> {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(output);
+        let mut questions = self.questions.iter();
+        let mut question = None;
+        for event in EventReader::new(generated) {
+            let event = event.map_err(io::Error::other)?;
+            match &event {
+                // The writer has its own declaration and indentation
+                ReaderEvent::StartDocument { .. } | ReaderEvent::Whitespace(_) => continue,
+                ReaderEvent::StartElement {
+                    name, attributes, ..
+                } if name.local_name == "question" => {
+                    let category = attributes
+                        .iter()
+                        .any(|attribute| attribute.value == "category");
+                    question = if category { None } else { questions.next() };
+                    if let Some(question) = question
+                        && question.regexp
+                    {
+                        writer
+                            .write(XmlEvent::start_element("question").attr("type", "regexp"))
+                            .map_err(io::Error::other)?;
+                        continue;
+                    }
+                }
+                ReaderEvent::EndElement { name } => match (question, name.local_name.as_str()) {
+                    (Some(question), "questiontext") => {
+                        question
+                            .write_files(&mut writer)
+                            .map_err(io::Error::other)?;
+                        writer
+                            .write(XmlEvent::end_element())
+                            .map_err(io::Error::other)?;
+                        question
+                            .write_feedback(&mut writer)
+                            .map_err(io::Error::other)?;
+                        continue;
+                    }
+                    (Some(question), "question") => {
+                        question
+                            .write_trailer(&mut writer)
+                            .map_err(io::Error::other)?;
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+            if let Some(event) = event.as_writer_event() {
+                writer.write(event).map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_text<W: Write>(
+    writer: &mut EventWriter<W>,
+    text: &str,
+    cdata: bool,
+) -> xml::writer::Result<()> {
+    writer.write(XmlEvent::start_element("text"))?;
+    if cdata {
+        writer.write(XmlEvent::cdata(text))?;
+    } else {
+        writer.write(XmlEvent::characters(text))?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn quiz_error(error: QuizError) -> io::Error {
+    io::Error::other(format!("Cannot write the quiz: {error:?}"))
+}
+
+/// Percent-encode a path segment like Moodle's `rawurlencode`
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_file() {
+        let file = EmbeddedFile {
+            name: "my data.txt".to_string(),
+            path: "/files/".to_string(),
+            contents: b"data".to_vec(),
+        };
+        assert_eq!(file.link(), "@@PLUGINFILE@@/files/my%20data.txt");
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("secret.sh");
+        std::fs::write(&source, "echo secret").unwrap();
+        let file = EmbeddedFile::read(&source, "secret.sh").unwrap();
+        assert_eq!(file.path, "/");
+        assert_eq!(file.link(), "@@PLUGINFILE@@/secret.sh");
+    }
+
+    #[test]
+    fn test_write_quiz() {
+        let mut question =
+            ShortAnswerQuestion::new("Task".to_string(), "<p>Find the flag</p>".to_string());
+        question.files.push(EmbeddedFile {
+            name: "secret.sh".to_string(),
+            path: "/".to_string(),
+            contents: b"echo secret".to_vec(),
+        });
+        question.answers.push(Answer::new(
+            100,
+            "flag{1}".to_string(),
+            Some("Correct!".to_string()),
+        ));
+        question.general_feedback = Some("<p>Read the script</p>".to_string());
+        question.penalty = Some(0.5);
+        question.hints = vec!["<p>Run it</p>".to_string()];
+        question.tags = vec!["shell".to_string()];
+        let mut quiz = Quiz::new(vec![question]);
+        quiz.category = Some("exam".to_string());
+        let mut output = Vec::new();
+        quiz.write(&mut output).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="category">
+    <category>
+      <text>$course$/exam/</text>
+    </category>
+  </question>
+  <question type="shortanswer">
+    <name>
+      <text>Task</text>
+    </name>
+    <questiontext format="html">
+      <text><![CDATA[<p>Find the flag</p>]]></text>
+      <file name="secret.sh" path="/" encoding="base64">ZWNobyBzZWNyZXQ=</file>
+    </questiontext>
+    <generalfeedback format="html">
+      <text><![CDATA[<p>Read the script</p>]]></text>
+    </generalfeedback>
+    <penalty>0.5</penalty>
+    <answer fraction="100" format="html">
+      <text>flag{1}</text>
+      <feedback format="html">
+        <text>Correct!</text>
+      </feedback>
+    </answer>
+    <usecase>0</usecase>
+    <hint format="html">
+      <text><![CDATA[<p>Run it</p>]]></text>
+    </hint>
+    <tags>
+      <tag>
+        <text>shell</text>
+      </tag>
+    </tags>
+  </question>
+</quiz>"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
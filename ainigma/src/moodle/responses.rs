@@ -0,0 +1,608 @@
+//! Moodle quiz responses mapped back to the variants of the exam
+//!
+//! The *Responses* report of a quiz, downloaded as CSV, has a row for each attempt and a `Response N` column for each
+//! question slot. The variant given in a slot is found from the `Right answer N` column, which is the first answer of
+//! the question: the flags of the variant. Without it, the `Question N` column is used if the question text shows the
+//! UUID of the variant. Only the English column names are recognised.
+//!
+//! The responses are compared to the flags of every built variant, so a flag of another variant reveals a shared flag.
+//! The user derived flags in a response are verified against the configured secrets for the UUID of the variant, which
+//! also finds the correct flags of variants whose builds were not loaded.
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
+
+use itertools::Itertools;
+use uuid::Uuid;
+
+use crate::build_process::{TaskBuildContainer, VariantStatus};
+use crate::config::UserDerived;
+use crate::flag_generator::Flag;
+
+/// Moodle writes this for a slot without a response
+const NO_RESPONSE: &str = "-";
+
+/// Built variant whose flags the responses are compared to
+#[derive(Debug, Clone)]
+pub struct KnownVariant {
+    pub task_id: String,
+    pub uuid: Uuid,
+    pub flags: Vec<Flag>,
+}
+
+impl KnownVariant {
+    /// The built variants of the tasks
+    pub fn from_builds(builds: &[TaskBuildContainer]) -> Vec<Self> {
+        builds
+            .iter()
+            .flat_map(|build| {
+                build
+                    .outputs
+                    .iter()
+                    .filter(|instance| instance.status == VariantStatus::Built)
+                    .map(|instance| KnownVariant {
+                        task_id: build.task.id.clone(),
+                        uuid: instance.uuid,
+                        flags: instance.stage_flags.clone(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Question slot of an attempt
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Slot {
+    pub number: usize,
+    pub response: String,
+    pub right_answer: Option<String>,
+    pub question: Option<String>,
+}
+
+/// Attempt of a student, a row of the responses report
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    /// Name of the student, followed by the email address if the report has it
+    pub student: String,
+    pub slots: Vec<Slot>,
+}
+
+/// Read the attempts of a responses report
+///
+/// The row of the overall average, which Moodle adds to the end, is skipped.
+pub fn read_responses<R: Read>(input: R) -> Result<Vec<Attempt>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|header| header.trim_start_matches('\u{feff}').trim().to_string())
+        .collect();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let name_columns: Vec<usize> = ["First name", "Last name", "Surname"]
+        .into_iter()
+        .filter_map(column)
+        .collect();
+    let email_column = column("Email address");
+    // Slot numbers of the columns
+    let numbered = |prefix: &str| -> HashMap<usize, usize> {
+        headers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, header)| {
+                let number = header.strip_prefix(prefix)?.parse().ok()?;
+                Some((number, index))
+            })
+            .collect()
+    };
+    let responses = numbered("Response ");
+    let right_answers = numbered("Right answer ");
+    let questions = numbered("Question ");
+
+    let mut attempts = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |index: Option<&usize>| {
+            index
+                .and_then(|index| record.get(*index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty() && *value != NO_RESPONSE)
+                .map(str::to_string)
+        };
+        let name = name_columns
+            .iter()
+            .filter_map(|index| field(Some(index)))
+            .join(" ");
+        let email = field(email_column.as_ref());
+        if name == "Overall average" || (name.is_empty() && email.is_none()) {
+            continue;
+        }
+        let student = match email {
+            Some(email) if name.is_empty() => email,
+            Some(email) => format!("{name} <{email}>"),
+            None => name,
+        };
+        let slots = responses
+            .keys()
+            .sorted()
+            .map(|number| Slot {
+                number: *number,
+                response: field(responses.get(number)).unwrap_or_default(),
+                right_answer: field(right_answers.get(number)),
+                question: field(questions.get(number)),
+            })
+            .collect();
+        attempts.push(Attempt { student, slots });
+    }
+    Ok(attempts)
+}
+
+/// Response of a student mapped to a variant
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseReport {
+    pub student: String,
+    pub slot: usize,
+    /// Task of the variant given to the student, if the variant was found in the builds
+    pub task: Option<String>,
+    /// UUID of the variant given to the student, also found from the question text
+    pub uuid: Option<Uuid>,
+    /// Flags of the variant in the response, user derived flags only if they are verified
+    pub correct_flags: usize,
+    pub total_flags: usize,
+    /// Identifiers of the user derived flags in the response which are correct for the UUID but not in the builds
+    pub unlisted_flags: Vec<String>,
+    /// Reasons to suspect that flags were shared
+    pub suspicions: Vec<String>,
+}
+
+/// Flags of a response
+struct MappedResponse<'a> {
+    attempt: &'a Attempt,
+    slot: &'a Slot,
+    /// Index of the variant given to the student
+    given: Option<usize>,
+    /// Whether the variant was only inferred from the flags in the response
+    inferred: bool,
+    /// UUID of the variant given to the student
+    uuid: Option<Uuid>,
+    /// Index of the variant and the stage of each flag in the response
+    found: BTreeSet<(usize, usize)>,
+}
+
+/// Map the responses to the variants and check their flags
+pub fn report(
+    attempts: &[Attempt],
+    variants: &[KnownVariant],
+    user_derived: &UserDerived,
+) -> Vec<ResponseReport> {
+    // The form of each flag a response must contain, in lowercase like the answers which are not case sensitive
+    let accepted: Vec<Vec<String>> = variants
+        .iter()
+        .map(|variant| {
+            variant
+                .flags
+                .iter()
+                .map(|flag| match flag {
+                    // The builder has made the flag from the seed
+                    Flag::RngSeed(flag) => flag.value().to_lowercase(),
+                    flag => flag.flag_string().to_lowercase(),
+                })
+                .collect()
+        })
+        .collect();
+    let find_flags = |text: &str| -> BTreeSet<(usize, usize)> {
+        let text = text.to_lowercase();
+        accepted
+            .iter()
+            .enumerate()
+            .flat_map(|(variant, flags)| {
+                flags
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, flag)| text.contains(flag.as_str()))
+                    .map(move |(stage, _)| (variant, stage))
+            })
+            .collect()
+    };
+    let single_variant = |found: &BTreeSet<(usize, usize)>| {
+        found
+            .iter()
+            .map(|(variant, _)| *variant)
+            .all_equal_value()
+            .ok()
+    };
+
+    let mut mapped = Vec::new();
+    for attempt in attempts {
+        for slot in &attempt.slots {
+            let found = find_flags(&slot.response);
+            let mut given = slot
+                .right_answer
+                .as_deref()
+                .and_then(|answer| single_variant(&find_flags(answer)))
+                .or_else(|| {
+                    let question = slot.question.as_deref()?.to_lowercase();
+                    variants
+                        .iter()
+                        .position(|variant| question.contains(&variant.uuid.to_string()))
+                });
+            let inferred = given.is_none() && !found.is_empty();
+            if inferred {
+                given = single_variant(&found);
+            }
+            if given.is_none() && found.is_empty() && slot.response.is_empty() {
+                continue;
+            }
+            let uuid = given
+                .map(|given| variants[given].uuid)
+                .or_else(|| slot.question.as_deref().and_then(find_uuid));
+            mapped.push(MappedResponse {
+                attempt,
+                slot,
+                given,
+                inferred,
+                uuid,
+                found,
+            });
+        }
+    }
+
+    // Students who were given each variant, and who submitted its flags
+    let mut receivers: HashMap<usize, BTreeSet<&str>> = HashMap::new();
+    let mut submitters: HashMap<usize, BTreeSet<&str>> = HashMap::new();
+    for response in &mapped {
+        let student = response.attempt.student.as_str();
+        if let Some(given) = response.given
+            && !response.inferred
+        {
+            receivers.entry(given).or_default().insert(student);
+        }
+        for (variant, _) in &response.found {
+            submitters.entry(*variant).or_default().insert(student);
+        }
+    }
+    let others = |students: Option<&BTreeSet<&str>>, student: &str| -> Vec<String> {
+        students
+            .into_iter()
+            .flatten()
+            .filter(|other| **other != student)
+            .map(|other| other.to_string())
+            .collect()
+    };
+
+    let mut reports = Vec::with_capacity(mapped.len());
+    for response in &mapped {
+        let student = response.attempt.student.as_str();
+        let mut suspicions = Vec::new();
+        let mut correct_flags = 0;
+        let mut total_flags = 0;
+        let tokens = flag_tokens(&response.slot.response);
+        // The identifier is part of the derived flag, so it is verified as written in the configuration
+        let verified = |identifier: &str| {
+            response.uuid.is_some_and(|uuid| {
+                tokens.iter().any(|(token, suffix)| {
+                    token.eq_ignore_ascii_case(identifier)
+                        && user_derived.verify(identifier, &uuid, suffix).is_some()
+                })
+            })
+        };
+        let stages: Vec<&str> = response
+            .given
+            .into_iter()
+            .flat_map(|given| variants[given].flags.iter().map(Flag::get_identifier))
+            .collect();
+        let unlisted_flags: Vec<String> = tokens
+            .iter()
+            .map(|(identifier, _)| *identifier)
+            .filter(|identifier| {
+                !stages
+                    .iter()
+                    .any(|stage| stage.eq_ignore_ascii_case(identifier))
+            })
+            .unique()
+            .filter(|identifier| verified(identifier) || verified(&identifier.to_lowercase()))
+            .map(str::to_string)
+            .collect();
+        if let Some(given) = response.given {
+            let variant = &variants[given];
+            total_flags = variant.flags.len();
+            for (stage, flag) in variant.flags.iter().enumerate() {
+                let found = response.found.contains(&(given, stage));
+                match flag {
+                    Flag::UserDerivedFlag(_) if verified(flag.get_identifier()) => {
+                        correct_flags += 1
+                    }
+                    Flag::UserDerivedFlag(_) if found => suspicions.push(format!(
+                        "flag of stage {} does not match the secrets",
+                        flag.get_identifier()
+                    )),
+                    Flag::UserDerivedFlag(_) => {}
+                    _ if found => correct_flags += 1,
+                    _ => {}
+                }
+            }
+            if response.inferred {
+                suspicions.push("variant inferred from the flags in the response".to_string());
+            } else {
+                let sharers = others(submitters.get(&given), student)
+                    .into_iter()
+                    .filter(|other| {
+                        !receivers
+                            .get(&given)
+                            .is_some_and(|receivers| receivers.contains(other.as_str()))
+                    })
+                    .collect::<Vec<_>>();
+                if !sharers.is_empty() {
+                    suspicions.push(format!(
+                        "flags of this variant submitted by {}",
+                        sharers.join(", ")
+                    ));
+                }
+            }
+        }
+        let foreign = response
+            .found
+            .iter()
+            .map(|(variant, _)| *variant)
+            .filter(|variant| Some(*variant) != response.given || response.inferred)
+            .dedup();
+        for variant in foreign {
+            if response.inferred {
+                let sharers = others(submitters.get(&variant), student);
+                if !sharers.is_empty() {
+                    suspicions.push(format!("same flags as {}", sharers.join(", ")));
+                }
+                continue;
+            }
+            let given_to = others(receivers.get(&variant), student);
+            let given_to = if given_to.is_empty() {
+                "nobody in the report".to_string()
+            } else {
+                given_to.join(", ")
+            };
+            suspicions.push(format!(
+                "flags of variant {} of task {} given to {}",
+                variants[variant].uuid, variants[variant].task_id, given_to
+            ));
+        }
+        reports.push(ResponseReport {
+            student: student.to_string(),
+            slot: response.slot.number,
+            task: response.given.map(|given| variants[given].task_id.clone()),
+            uuid: response.uuid,
+            correct_flags,
+            total_flags,
+            unlisted_flags,
+            suspicions,
+        });
+    }
+    reports
+}
+
+/// The `identifier:suffix` tokens of the text, which are the flags with or without `flag{}` around them
+fn flag_tokens(text: &str) -> Vec<(&str, &str)> {
+    text.split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')))
+        .filter_map(|token| {
+            let (rest, suffix) = token.rsplit_once(':')?;
+            let identifier = rest.rsplit(':').next()?;
+            (!identifier.is_empty()
+                && !suffix.is_empty()
+                && suffix.chars().all(|c| c.is_ascii_hexdigit()))
+            .then_some((identifier, suffix))
+        })
+        .collect()
+}
+
+/// The first hyphenated UUID in the text
+fn find_uuid(text: &str) -> Option<Uuid> {
+    text.split(|c: char| !(c.is_ascii_hexdigit() || c == '-'))
+        .filter(|token| token.len() == 36)
+        .find_map(|token| Uuid::try_parse(token).ok())
+}
+
+/// Write the report as CSV: `student,slot,task,uuid,correct_flags,total_flags,unlisted_flags,suspicions`
+pub fn write_report<W: Write>(reports: &[ResponseReport], output: W) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record([
+        "student",
+        "slot",
+        "task",
+        "uuid",
+        "correct_flags",
+        "total_flags",
+        "unlisted_flags",
+        "suspicions",
+    ])?;
+    for report in reports {
+        let uuid = report.uuid.map(|uuid| uuid.to_string()).unwrap_or_default();
+        writer.write_record([
+            report.student.as_str(),
+            report.slot.to_string().as_str(),
+            report.task.as_deref().unwrap_or_default(),
+            uuid.as_str(),
+            report.correct_flags.to_string().as_str(),
+            report.total_flags.to_string().as_str(),
+            report.unlisted_flags.join("; ").as_str(),
+            report.suspicions.join("; ").as_str(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SecretSource, VersionedSecret};
+    use crate::flag_generator::Algorithm;
+
+    const SECRET: &str = "secret";
+
+    fn variant(uuid: u128) -> KnownVariant {
+        let uuid = Uuid::from_u128(uuid);
+        KnownVariant {
+            task_id: "task001".to_string(),
+            uuid,
+            flags: vec![Flag::new_user_flag(
+                "task001".to_string(),
+                &Algorithm::HMAC_SHA3_256,
+                SECRET,
+                "task001",
+                &uuid,
+            )],
+        }
+    }
+
+    fn user_derived() -> UserDerived {
+        let mut user_derived = UserDerived::new(
+            Algorithm::HMAC_SHA3_256,
+            vec![VersionedSecret::new(
+                1,
+                SecretSource::Value(SECRET.to_string()),
+            )],
+            None,
+        )
+        .unwrap();
+        user_derived
+            .resolve_secrets(std::path::Path::new("."), true)
+            .unwrap();
+        user_derived
+    }
+
+    #[test]
+    fn test_read_responses() {
+        let csv = "\u{feff}Last name,First name,Email address,State,Grade/1.00,Response 1,Right answer 1\n\
+                   Doe,Jane,jane@example.com,Finished,1.00,flag{a},flag{a}\n\
+                   Roe,Rick,,Finished,0.00,-,flag{b}\n\
+                   Overall average,,,,0.50,,\n";
+        let attempts = read_responses(csv.as_bytes()).unwrap();
+        assert_eq!(
+            attempts,
+            [
+                Attempt {
+                    student: "Jane Doe <jane@example.com>".to_string(),
+                    slots: vec![Slot {
+                        number: 1,
+                        response: "flag{a}".to_string(),
+                        right_answer: Some("flag{a}".to_string()),
+                        question: None,
+                    }],
+                },
+                Attempt {
+                    student: "Rick Roe".to_string(),
+                    slots: vec![Slot {
+                        number: 1,
+                        response: String::new(),
+                        right_answer: Some("flag{b}".to_string()),
+                        question: None,
+                    }],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_flag() {
+        let variants = [variant(1), variant(2)];
+        let attempt = |student: &str, response: &Flag, given: &Flag| Attempt {
+            student: student.to_string(),
+            slots: vec![Slot {
+                number: 1,
+                response: format!("the flag is {}", response.encased().to_uppercase()),
+                right_answer: Some(given.encased().to_string()),
+                question: None,
+            }],
+        };
+        let attempts = [
+            attempt("alice", &variants[0].flags[0], &variants[0].flags[0]),
+            attempt("bob", &variants[0].flags[0], &variants[1].flags[0]),
+        ];
+        let reports = report(&attempts, &variants, &user_derived());
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].task.as_deref(), Some("task001"));
+        assert_eq!(reports[0].uuid, Some(variants[0].uuid));
+        assert_eq!(reports[0].correct_flags, 1);
+        assert_eq!(
+            reports[0].suspicions,
+            ["flags of this variant submitted by bob"]
+        );
+        assert_eq!(reports[1].correct_flags, 0);
+        assert_eq!(reports[1].total_flags, 1);
+        assert_eq!(
+            reports[1].suspicions,
+            [format!(
+                "flags of variant {} of task task001 given to alice",
+                variants[0].uuid
+            )]
+        );
+
+        let mut output = Vec::new();
+        write_report(&reports, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(
+            "student,slot,task,uuid,correct_flags,total_flags,unlisted_flags,suspicions\n"
+        ));
+        assert!(output.contains(&format!("alice,1,task001,{},1,1,,", variants[0].uuid)));
+    }
+
+    #[test]
+    fn test_unverified_and_inferred_variant() {
+        let mut forged = variant(1);
+        forged.flags = vec![Flag::new_user_flag(
+            "task001".to_string(),
+            &Algorithm::HMAC_SHA3_256,
+            "other secret",
+            "task001",
+            &forged.uuid,
+        )];
+        let variants = [forged];
+        let attempts = [Attempt {
+            student: "alice".to_string(),
+            slots: vec![Slot {
+                number: 3,
+                response: variants[0].flags[0].flag_string(),
+                right_answer: None,
+                question: None,
+            }],
+        }];
+        let reports = report(&attempts, &variants, &user_derived());
+        assert_eq!(reports[0].slot, 3);
+        assert_eq!(reports[0].correct_flags, 0);
+        assert_eq!(
+            reports[0].suspicions,
+            [
+                "flag of stage task001 does not match the secrets",
+                "variant inferred from the flags in the response"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flag_tokens() {
+        assert_eq!(
+            flag_tokens("flag{task001:ab12}, Stage-2:CD34 answer:task003:ef and task004:xyz"),
+            [("task001", "ab12"), ("Stage-2", "CD34"), ("task003", "ef")]
+        );
+        let uuid = Uuid::from_u128(7);
+        assert_eq!(find_uuid(&format!("<p>Variant {uuid}</p>")), Some(uuid));
+        assert_eq!(find_uuid(&uuid.simple().to_string()), None);
+    }
+
+    #[test]
+    fn test_flag_of_unloaded_variant() {
+        let unloaded = variant(2);
+        let variants = [variant(1)];
+        let attempts = [Attempt {
+            student: "alice".to_string(),
+            slots: vec![Slot {
+                number: 1,
+                response: unloaded.flags[0].encased().to_string(),
+                right_answer: None,
+                question: Some(format!("Find the flag of variant {}", unloaded.uuid)),
+            }],
+        }];
+        let reports = report(&attempts, &variants, &user_derived());
+        assert_eq!(reports[0].task, None);
+        assert_eq!(reports[0].uuid, Some(unloaded.uuid));
+        assert_eq!(reports[0].unlisted_flags, ["task001"]);
+        assert!(reports[0].suspicions.is_empty());
+    }
+}
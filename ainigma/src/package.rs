@@ -0,0 +1,195 @@
+//! Packaging the resource files of a variant into a single archive
+//!
+//! The archive is written into the variant directory next to the original files, which are kept for the server.
+//! Archives are reproducible: the entries are sorted and have fixed timestamps and owners.
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use zip::write::SimpleFileOptions;
+
+use crate::build_process::{IntermediateOutput, OutputItem};
+use crate::config::{ArchiveFormat, OutputKind, PackageConfig};
+use crate::errors::BuildError;
+
+/// Archive of the resource files, distributed instead of the files themselves
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Package {
+    /// The archive, with its size and SHA-256
+    pub item: OutputItem,
+}
+
+/// Create the archive of the resource files of the variant. Variants without resource files are not packaged.
+pub fn package_instance(
+    config: &PackageConfig,
+    task_id: &str,
+    instance: &mut IntermediateOutput,
+) -> Result<(), BuildError> {
+    let resources = instance.get_resource_files();
+    if resources.is_empty() {
+        return Ok(());
+    }
+    let mut entries: Vec<(String, PathBuf)> = resources
+        .iter()
+        .map(|item| {
+            let path = instance.task_instance_dir.join(item.kind.get_filename());
+            (entry_name(&instance.task_instance_dir, &path), path)
+        })
+        .collect();
+    entries.sort();
+
+    let path = instance
+        .task_instance_dir
+        .join(config.file_name(task_id, &instance.uuid));
+    let written = match config.format {
+        ArchiveFormat::Zip => write_zip(&path, &entries),
+        ArchiveFormat::TarGz => write_tar_gz(&path, &entries),
+    };
+    written.map_err(|e| {
+        BuildError::PackagingError(format!(
+            "Cannot write the archive '{}' of task {}: {}",
+            path.display(),
+            task_id,
+            e
+        ))
+    })?;
+    let mut item = OutputItem::new(OutputKind::Resource(path.clone()));
+    item.update_integrity(&path).map_err(|e| {
+        BuildError::PackagingError(format!("Cannot read '{}': {}", path.display(), e))
+    })?;
+    tracing::debug!(
+        "Packaged {} files of task {} variant {} into '{}'",
+        entries.len(),
+        task_id,
+        instance.uuid,
+        path.display()
+    );
+    instance.package = Some(Package { item });
+    Ok(())
+}
+
+/// Path inside the archive, relative to the variant directory
+pub(crate) fn entry_name(instance_dir: &Path, path: &Path) -> String {
+    // The outputs have canonical paths after the validation
+    let base = instance_dir
+        .canonicalize()
+        .unwrap_or_else(|_| instance_dir.to_path_buf());
+    let relative = path
+        .strip_prefix(&base)
+        .or_else(|_| path.strip_prefix(instance_dir))
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| path.file_name().map(PathBuf::from).unwrap_or_default());
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+fn file_mode(path: &Path) -> io::Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let executable = fs::metadata(path)?.permissions().mode() & 0o111 != 0;
+    Ok(if executable { 0o755 } else { 0o644 })
+}
+
+fn write_zip(path: &Path, entries: &[(String, PathBuf)]) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+    for (name, source) in entries {
+        let options = SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip::DateTime::default())
+            .unix_permissions(file_mode(source)?);
+        zip.start_file(name.as_str(), options)
+            .map_err(io::Error::other)?;
+        io::copy(&mut fs::File::open(source)?, &mut zip)?;
+    }
+    zip.finish().map_err(io::Error::other)?.flush()
+}
+
+fn write_tar_gz(path: &Path, entries: &[(String, PathBuf)]) -> io::Result<()> {
+    let encoder = GzEncoder::new(fs::File::create(path)?, Compression::default());
+    let mut tar = tar::Builder::new(encoder);
+    for (name, source) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(fs::metadata(source)?.len());
+        header.set_mode(file_mode(source)?);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        tar.append_data(&mut header, name, fs::File::open(source)?)?;
+    }
+    tar.into_inner()?.finish()?.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::sha256_file;
+    use std::io::Read;
+    use uuid::Uuid;
+
+    fn instance(dir: &Path) -> IntermediateOutput {
+        fs::create_dir(dir.join("files")).unwrap();
+        fs::write(dir.join("secret.sh"), "echo secret").unwrap();
+        fs::write(dir.join("files/data.txt"), "data").unwrap();
+        IntermediateOutput::new(
+            Uuid::now_v7(),
+            Vec::new(),
+            dir.to_path_buf(),
+            vec![
+                OutputItem::new(OutputKind::Resource("secret.sh".into())),
+                OutputItem::new(OutputKind::Resource("files/data.txt".into())),
+                OutputItem::new(OutputKind::Readme("readme.txt".into())),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_zip_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut instance = instance(dir.path());
+        let config = PackageConfig {
+            format: ArchiveFormat::Zip,
+            name: "{task_id}".to_string(),
+        };
+        package_instance(&config, "task001", &mut instance).unwrap();
+        let package = instance.package.clone().unwrap();
+        let path = package.item.kind.get_filename();
+        assert_eq!(path, dir.path().join("task001.zip"));
+        assert_eq!(package.item.sha256, Some(sha256_file(path).unwrap()));
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        let names: Vec<_> = archive.file_names().collect();
+        assert_eq!(names, ["files/data.txt", "secret.sh"]);
+        let mut contents = String::new();
+        archive
+            .by_name("secret.sh")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "echo secret");
+
+        // The same files produce the same archive
+        package_instance(&config, "task001", &mut instance).unwrap();
+        assert_eq!(instance.package.unwrap().item.sha256, package.item.sha256);
+    }
+
+    #[test]
+    fn test_tar_gz_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut instance = instance(dir.path());
+        let config = PackageConfig {
+            format: ArchiveFormat::TarGz,
+            name: "files".to_string(),
+        };
+        package_instance(&config, "task001", &mut instance).unwrap();
+        let path = dir.path().join("files.tar.gz");
+        let mut archive =
+            tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(&path).unwrap()));
+        let names: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+        assert_eq!(names, ["files/data.txt", "secret.sh"]);
+    }
+}
@@ -0,0 +1,234 @@
+//! Checking that the builds of a task are reproducible
+//!
+//! Every variant is built again with the same UUID, and the files of both builds are compared by their hashes.
+//! Reproducible builds make it possible to regenerate the files of a student later, without storing them.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::build_process::{
+    BuildOptions, IntermediateOutput, TaskBuildContainer, build_sequential,
+};
+use crate::checksum::sha256_file;
+use crate::config::{
+    DEFAULT_BUILD_LOG, DEFAULT_BUILD_MANIFEST, FlagVariantKind, ModuleConfiguration, Task,
+};
+use crate::errors::BuildError;
+
+/// File or flag which is not identical between two builds of the same variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub uuid: uuid::Uuid,
+    /// Path relative to the variant directory, or the identifier of a flag
+    pub item: String,
+    pub kind: DifferenceKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifferenceKind {
+    /// The file exists in both builds with different contents
+    Content,
+    /// The file exists only in the first build
+    MissingFromSecond,
+    /// The file exists only in the second build
+    MissingFromFirst,
+    /// The builder produced a different flag
+    Flag,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self.kind {
+            DifferenceKind::Content => "has different contents",
+            DifferenceKind::MissingFromSecond => "exists only in the first build",
+            DifferenceKind::MissingFromFirst => "exists only in the second build",
+            DifferenceKind::Flag => "is a flag with a different value",
+        };
+        write!(f, "'{}' {}", self.item, description)
+    }
+}
+
+/// Check that the task can be reproduced at all: every flag must be derived from the UUID
+pub fn check_reproducible_task(task: &Task) -> Result<(), BuildError> {
+    if task.batch.is_some() {
+        return Err(BuildError::NotReproducible(format!(
+            "task {} is built in batch mode, which does not support given UUIDs",
+            task.id
+        )));
+    }
+    if task
+        .stages
+        .iter()
+        .any(|stage| stage.flag.kind == FlagVariantKind::PureRandom)
+    {
+        return Err(BuildError::NotReproducible(format!(
+            "task {} has pure_random flags, which are different on every build",
+            task.id
+        )));
+    }
+    Ok(())
+}
+
+/// Build every variant of the build again into `work_dir` and compare the results
+///
+/// The build cache is not used, so the builder runs every time.
+pub fn check_reproducible_build(
+    module_config: &ModuleConfiguration,
+    build: &TaskBuildContainer,
+    work_dir: &Path,
+) -> Result<Vec<Difference>, BuildError> {
+    check_reproducible_task(&build.task)?;
+    let mut differences = Vec::new();
+    for (i, instance) in build.outputs.iter().enumerate() {
+        let rebuilt = build_sequential(
+            module_config,
+            &build.task,
+            instance.uuid,
+            work_dir,
+            i + 1,
+            false,
+            &BuildOptions::default(),
+        )?;
+        let found = compare_instances(instance, &rebuilt)?;
+        for difference in &found {
+            tracing::warn!(
+                "Task {} variant {} is not reproducible: {}",
+                build.task.id,
+                instance.uuid,
+                difference
+            );
+        }
+        differences.extend(found);
+    }
+    Ok(differences)
+}
+
+/// Compare the flags and all the files in the variant directories, except the build manifest and log
+pub fn compare_instances(
+    first: &IntermediateOutput,
+    second: &IntermediateOutput,
+) -> Result<Vec<Difference>, BuildError> {
+    let mut differences = Vec::new();
+    for flag in &first.stage_flags {
+        let same = second.stage_flags.iter().any(|other| {
+            other.get_identifier() == flag.get_identifier()
+                && other.flag_string() == flag.flag_string()
+        });
+        if !same {
+            differences.push(Difference {
+                uuid: first.uuid,
+                item: flag.get_identifier().to_string(),
+                kind: DifferenceKind::Flag,
+            });
+        }
+    }
+
+    let first_files = hash_directory(&first.task_instance_dir)?;
+    let mut second_files = hash_directory(&second.task_instance_dir)?;
+    for (path, hash) in first_files {
+        let kind = match second_files.remove(&path) {
+            Some(other) if other == hash => continue,
+            Some(_) => DifferenceKind::Content,
+            None => DifferenceKind::MissingFromSecond,
+        };
+        differences.push(Difference {
+            uuid: first.uuid,
+            item: path.display().to_string(),
+            kind,
+        });
+    }
+    differences.extend(second_files.into_keys().map(|path| Difference {
+        uuid: first.uuid,
+        item: path.display().to_string(),
+        kind: DifferenceKind::MissingFromFirst,
+    }));
+    Ok(differences)
+}
+
+/// Hashes of the files in the directory by their relative paths
+fn hash_directory(root: &Path) -> Result<BTreeMap<PathBuf, String>, BuildError> {
+    let mut hashes = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = std::fs::read_dir(&dir).map_err(|e| read_error(&dir, e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| read_error(&dir, e))?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            if relative == Path::new(DEFAULT_BUILD_MANIFEST)
+                || relative == Path::new(DEFAULT_BUILD_LOG)
+            {
+                continue;
+            }
+            let hash = sha256_file(&path).map_err(|e| read_error(&path, e))?;
+            hashes.insert(relative, hash);
+        }
+    }
+    Ok(hashes)
+}
+
+fn read_error(path: &Path, error: std::io::Error) -> BuildError {
+    BuildError::OutputVerificationFailed(format!(
+        "Cannot read '{}' for the comparison: {}",
+        path.display(),
+        error
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flag_generator::Flag;
+
+    fn instance(dir: &Path, flags: Vec<Flag>) -> IntermediateOutput {
+        IntermediateOutput::new(uuid::Uuid::nil(), flags, dir.to_path_buf(), Vec::new())
+    }
+
+    #[test]
+    fn test_compare_instances() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        for dir in [first.path(), second.path()] {
+            std::fs::create_dir(dir.join("nested")).unwrap();
+            std::fs::write(dir.join("readme.txt"), "same").unwrap();
+            std::fs::write(
+                dir.join(DEFAULT_BUILD_MANIFEST),
+                dir.to_string_lossy().as_bytes(),
+            )
+            .unwrap();
+        }
+        std::fs::write(first.path().join("nested/info.txt"), "12:00").unwrap();
+        std::fs::write(second.path().join("nested/info.txt"), "12:01").unwrap();
+        std::fs::write(first.path().join("only-first"), "").unwrap();
+
+        let flag = Flag::new_random_flag("task001".to_string(), 16);
+        let other = Flag::new_random_flag("task001".to_string(), 16);
+        let differences = compare_instances(
+            &instance(first.path(), vec![flag.clone()]),
+            &instance(second.path(), vec![other]),
+        )
+        .unwrap();
+        let found: Vec<_> = differences
+            .iter()
+            .map(|difference| (difference.item.as_str(), difference.kind))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("task001", DifferenceKind::Flag),
+                ("nested/info.txt", DifferenceKind::Content),
+                ("only-first", DifferenceKind::MissingFromSecond),
+            ]
+        );
+
+        let same = compare_instances(
+            &instance(first.path(), vec![flag.clone()]),
+            &instance(first.path(), vec![flag]),
+        )
+        .unwrap();
+        assert!(same.is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+//! Sandboxed execution of the build processes
+//!
+//! The builder is run with [bubblewrap](https://github.com/containers/bubblewrap) in new Linux namespaces.
+//! Only the system directories and the task directory are visible, all of them read-only.
+//! The output directories of the build are the only writable locations.
+//! The environment of the parent process is not inherited, only the build environment is passed.
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::SandboxConfig;
+use crate::errors::BuildError;
+
+const BWRAP_PROGRAM: &str = "bwrap";
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+/// System locations needed to run typical build tools, mounted read-only when they exist
+const SYSTEM_DIRECTORIES: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/nix"];
+
+/// Create a command which runs the program inside the sandbox
+///
+/// `work_dir` is mounted read-only and used as the working directory.
+/// `writable_dirs` must exist, and they are mounted at the same paths.
+pub fn sandboxed_command(
+    config: &SandboxConfig,
+    program: &str,
+    args: &[&str],
+    work_dir: &Path,
+    writable_dirs: &[PathBuf],
+    build_envs: HashMap<String, String>,
+) -> Result<Command, BuildError> {
+    let work_dir = absolute(work_dir)?;
+    let writable_dirs = writable_dirs
+        .iter()
+        .map(|dir| absolute(dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut command = Command::new(BWRAP_PROGRAM);
+    command
+        .args(bwrap_args(config, &work_dir, &writable_dirs))
+        .arg("--")
+        .arg(program)
+        .args(args)
+        .env_clear()
+        .env(
+            "PATH",
+            std::env::var("PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string()),
+        )
+        .env("HOME", "/tmp")
+        .envs(build_envs)
+        .current_dir(&work_dir);
+
+    let limits = config.clone();
+    // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+    unsafe {
+        command.pre_exec(move || set_limits(&limits));
+    }
+    Ok(command)
+}
+
+fn bwrap_args(config: &SandboxConfig, work_dir: &Path, writable_dirs: &[PathBuf]) -> Vec<String> {
+    let mut args = Vec::new();
+    for dir in SYSTEM_DIRECTORIES {
+        args.extend([
+            "--ro-bind-try".to_string(),
+            dir.to_string(),
+            dir.to_string(),
+        ]);
+    }
+    args.extend(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"].map(String::from));
+    let work_dir = work_dir.display().to_string();
+    args.extend(["--ro-bind".to_string(), work_dir.clone(), work_dir.clone()]);
+    // Output directories can be inside the task directory, so they must be mounted after it
+    for dir in writable_dirs {
+        let dir = dir.display().to_string();
+        args.extend(["--bind".to_string(), dir.clone(), dir]);
+    }
+    args.push("--unshare-all".to_string());
+    if config.network {
+        args.push("--share-net".to_string());
+    }
+    args.extend(["--die-with-parent", "--new-session", "--chdir"].map(String::from));
+    args.push(work_dir);
+    args
+}
+
+pub(crate) fn absolute(path: &Path) -> Result<PathBuf, BuildError> {
+    path.canonicalize().map_err(|e| {
+        BuildError::SandboxError(format!(
+            "Cannot resolve the path '{}' for the sandbox: {e}",
+            path.display()
+        ))
+    })
+}
+
+/// Limits of the builder process, inherited by its children
+///
+/// `RLIMIT_NPROC` counts all the processes of the real user ID, not only the ones in the sandbox. It must leave room
+/// for the other processes of the user, and the processes of the sandbox still use the quota of the user.
+fn set_limits(config: &SandboxConfig) -> std::io::Result<()> {
+    if let Some(seconds) = config.cpu_time {
+        set_limit(libc::RLIMIT_CPU, seconds)?;
+    }
+    if let Some(megabytes) = config.memory {
+        set_limit(libc::RLIMIT_AS, megabytes.saturating_mul(1024 * 1024))?;
+    }
+    if let Some(processes) = config.processes {
+        set_limit(libc::RLIMIT_NPROC, processes)?;
+    }
+    Ok(())
+}
+
+fn set_limit(resource: Resource, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    // SAFETY: the pointer refers to a valid rlimit struct for the duration of the call
+    if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bwrap_args() {
+        let config = SandboxConfig::default();
+        let args = bwrap_args(
+            &config,
+            Path::new("/tasks/task001"),
+            &[PathBuf::from("/tasks/task001/output/uuid")],
+        );
+        let joined = args.join(" ");
+        assert!(joined.contains("--ro-bind /tasks/task001 /tasks/task001"));
+        // Writable output is mounted over the read-only task directory
+        let ro = joined.find("--ro-bind /tasks/task001 ").unwrap();
+        let rw = joined
+            .find("--bind /tasks/task001/output/uuid /tasks/task001/output/uuid")
+            .unwrap();
+        assert!(ro < rw);
+        assert!(args.contains(&"--unshare-all".to_string()));
+        assert!(!args.contains(&"--share-net".to_string()));
+        assert!(joined.ends_with("--chdir /tasks/task001"));
+
+        let config = SandboxConfig {
+            network: true,
+            ..Default::default()
+        };
+        let args = bwrap_args(&config, Path::new("/tasks/task001"), &[]);
+        assert!(args.contains(&"--share-net".to_string()));
+    }
+
+    #[test]
+    fn test_sandboxed_command_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = sandboxed_command(
+            &SandboxConfig::default(),
+            "sh",
+            &["build.sh"],
+            dir.path(),
+            &[dir.path().to_path_buf()],
+            HashMap::from([("TASK_ID".to_string(), "task001".to_string())]),
+        )
+        .unwrap();
+        assert_eq!(command.get_program(), BWRAP_PROGRAM);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(&args[args.len() - 3..], ["--", "sh", "build.sh"]);
+        let envs: HashMap<_, _> = command.get_envs().collect();
+        assert_eq!(
+            envs[std::ffi::OsStr::new("TASK_ID")],
+            Some("task001".as_ref())
+        );
+        assert_eq!(envs[std::ffi::OsStr::new("HOME")], Some("/tmp".as_ref()));
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        let result = sandboxed_command(
+            &SandboxConfig::default(),
+            "sh",
+            &[],
+            Path::new("/does/not/exist"),
+            &[],
+            HashMap::new(),
+        );
+        assert!(matches!(result, Err(BuildError::SandboxError(_))));
+    }
+}
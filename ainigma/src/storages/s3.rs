@@ -1,11 +1,13 @@
 use super::CloudStorage;
 use super::FileObjects;
 use crate::build_process::OutputItem;
+use crate::checksum::hex_to_base64;
 use crate::errors::{AccessError, CloudStorageError};
 use futures::future::try_join_all;
 
 use aws_sdk_s3::presigning::PresigningConfig;
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumAlgorithm, ChecksumMode};
 use aws_sdk_s3::{Client, config::Region};
 use serde_json::json;
 
@@ -14,6 +16,9 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 
+/// User metadata key of the uploaded objects, see [`OutputItem::size`]
+pub const SIZE_METADATA: &str = "size";
+
 #[derive(Debug)]
 pub struct S3Storage {
     client: Client,
@@ -61,6 +66,49 @@ impl S3Storage {
             link_expiration_days,
         })
     }
+    /// Check that the uploaded object has the size and the checksum of the local file
+    ///
+    /// The SHA-256 is the checksum that the storage computed on upload, not a value sent by us.
+    pub async fn verify_object(
+        &self,
+        file_key: &str,
+        item: &OutputItem,
+    ) -> Result<(), CloudStorageError> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(file_key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+            .map_err(|e| CloudStorageError::AWSSdkError(e.to_string()))?;
+        if let Some(size) = item.size
+            && head
+                .content_length()
+                .and_then(|length| u64::try_from(length).ok())
+                != Some(size)
+        {
+            return Err(CloudStorageError::IntegrityError(format!(
+                "'{}' has {} bytes in the bucket, expected {}",
+                file_key,
+                head.content_length().unwrap_or_default(),
+                size
+            )));
+        }
+        if let Some(expected) = item.sha256.as_deref().and_then(hex_to_base64) {
+            let stored = head.checksum_sha256();
+            if stored != Some(expected.as_str()) {
+                return Err(CloudStorageError::IntegrityError(format!(
+                    "'{}' has the SHA-256 checksum {} in the bucket, expected {}",
+                    file_key,
+                    stored.unwrap_or("<missing>"),
+                    expected
+                )));
+            }
+        }
+        Ok(())
+    }
     /// Makes all files in the bucket available for download
     /// Does not give listing permissions
     pub async fn set_public_access(&self) -> Result<(), CloudStorageError> {
@@ -154,20 +202,29 @@ impl CloudStorage for S3Storage {
             let task = async {
                 match body {
                     Ok(b) => {
-                        let response = self
+                        let mut request = self
                             .client
                             .put_object()
                             .bucket(&self.bucket)
                             .key(&file_key)
-                            .body(b)
-                            .send()
-                            .await;
+                            .body(b);
+                        // The storage rejects the upload if the body does not match the checksum
+                        if let Some(checksum) = file.1.sha256.as_deref().and_then(hex_to_base64) {
+                            request = request
+                                .checksum_algorithm(ChecksumAlgorithm::Sha256)
+                                .checksum_sha256(checksum);
+                        }
+                        if let Some(size) = file.1.size {
+                            request = request.metadata(SIZE_METADATA, size.to_string());
+                        }
+                        let response = request.send().await;
                         match response {
                             Ok(r) => {
                                 tracing::debug!(
                                     "Created or updated the file with expiration: {}",
                                     r.expiration.unwrap_or_default()
                                 );
+                                self.verify_object(&file_key, &file.1).await?;
                                 let presigned_request = self
                                     .client
                                     .get_object()
@@ -1,6 +1,7 @@
 use crate::build_process::TaskBuildContainer;
 use crate::config::ModuleConfiguration;
 use crate::errors::CloudStorageError;
+use crate::events::{BuildEvent, EventSink};
 use crate::storages::s3::S3Storage;
 use crate::storages::storage::{CloudStorage, FileObjects};
 use tokio::runtime::Runtime;
@@ -9,6 +10,7 @@ pub fn s3_upload<'a>(
     config: &'a ModuleConfiguration,
     mut container: TaskBuildContainer<'a>,
     runtime: &Runtime,
+    events: &EventSink,
 ) -> Result<TaskBuildContainer<'a>, Box<dyn std::error::Error>> {
     // Check if the bucket exists
     let storage = S3Storage::from_config(config.deployment.upload.clone());
@@ -61,13 +63,18 @@ pub fn s3_upload<'a>(
             file.uuid
         );
         let future = async {
-            match FileObjects::new(dst_location, file.get_resource_files())
+            match FileObjects::new(dst_location, file.get_distributed_files())
                 .map_err(CloudStorageError::FileObjectError)
             {
                 Ok(files) => {
                     let items = storage
                         .upload(files, config.deployment.upload.use_pre_signed)
                         .await?;
+                    events.emit(BuildEvent::UploadFinished {
+                        task_id: container.task.id.clone(),
+                        uuid: file.uuid,
+                        files: items.len(),
+                    });
                     file.update_files(items);
                     Ok(file)
                 }
@@ -0,0 +1,212 @@
+//! Rendering readme and instruction templates with the values of a variant
+//!
+//! Templates refer to values with `{{ name }}`. Unknown names are errors, so mistakes are found at build time.
+//! The available values are listed in [`variables`].
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::build_process::IntermediateOutput;
+use crate::config::{OutputKind, Task};
+use crate::errors::BuildError;
+
+/// Render the template with the given values
+pub fn render(template: &str, variables: &BTreeMap<String, String>) -> Result<String, BuildError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            return Err(BuildError::TemplateError(format!(
+                "'{{{{' at byte {} is not closed",
+                template.len() - rest.len() + start
+            )));
+        };
+        let name = rest[start + 2..start + end].trim();
+        match variables.get(name) {
+            Some(value) => rendered.push_str(value),
+            None => {
+                return Err(BuildError::TemplateError(format!(
+                    "unknown variable '{name}'"
+                )));
+            }
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Values of the variant available in the templates
+///
+/// - `task.id`, `task.name`, `task.description` and `task.points`
+/// - `uuid`
+/// - `stage.<id>.name`, `stage.<id>.description` and `stage.<id>.points` for each stage. A single stage has the task ID.
+/// - `links.<file name>` for each resource file: the uploaded link, or the file name if the files were not uploaded.
+///   The link of the package is available with the file name of the archive.
+/// - `meta.url` and `meta.<key>` from the meta output of the builder
+pub fn variables(task: &Task, instance: &IntermediateOutput) -> BTreeMap<String, String> {
+    let mut variables = BTreeMap::from([
+        ("task.id".to_string(), task.id.clone()),
+        ("task.name".to_string(), task.name.clone()),
+        ("task.description".to_string(), task.description.clone()),
+        ("task.points".to_string(), task.points.to_string()),
+        ("uuid".to_string(), instance.uuid.to_string()),
+    ]);
+
+    for (stage, points) in task.stages.iter().zip(task.stage_points()) {
+        let id = stage.id.as_deref().unwrap_or(&task.id);
+        let name = stage.name.clone().unwrap_or_else(|| task.name.clone());
+        let description = stage
+            .description
+            .clone()
+            .unwrap_or_else(|| task.description.clone());
+        variables.insert(format!("stage.{id}.name"), name);
+        variables.insert(format!("stage.{id}.description"), description);
+        variables.insert(format!("stage.{id}.points"), points.to_string());
+    }
+
+    let distributed = instance.get_resource_files().into_iter().chain(
+        instance
+            .package
+            .as_ref()
+            .map(|package| package.item.clone()),
+    );
+    for item in distributed {
+        if let OutputKind::Resource(path) = &item.kind
+            && let Some(name) = path.file_name()
+        {
+            let name = name.to_string_lossy().into_owned();
+            let link = item.link.clone().unwrap_or_else(|| name.clone());
+            variables.insert(format!("links.{name}"), link);
+        }
+    }
+
+    if let Some(meta) = &instance.meta {
+        if let Some(url) = &meta.url {
+            variables.insert("meta.url".to_string(), url.clone());
+        }
+        for (key, value) in &meta.metadata {
+            let value = match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            variables.insert(format!("meta.{key}"), value);
+        }
+    }
+    variables
+}
+
+/// Instructions of the variant: the rendered readme template of the task, or the readme file written by the builder
+///
+/// The template path is relative to `task_directory`.
+pub fn instructions(
+    task: &Task,
+    task_directory: &Path,
+    instance: &IntermediateOutput,
+) -> Result<String, BuildError> {
+    match &task.build.readme_template {
+        Some(template) => {
+            let path = task_directory.join(template);
+            let template = std::fs::read_to_string(&path).map_err(|e| {
+                BuildError::TemplateError(format!(
+                    "Cannot read the readme template '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            render(&template, &variables(task, instance))
+        }
+        None => {
+            let readme = instance.get_readme().ok_or_else(|| {
+                BuildError::OutputVerificationFailed(format!(
+                    "Task {} has neither a readme output nor a readme template",
+                    task.id
+                ))
+            })?;
+            let path = instance.task_instance_dir.join(readme.kind.get_filename());
+            std::fs::read_to_string(&path).map_err(|e| {
+                BuildError::OutputVerificationFailed(format!(
+                    "Cannot read the readme '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_process::{Challenge, OutputItem};
+    use uuid::Uuid;
+
+    fn task() -> Task {
+        toml::from_str(
+            r#"
+            id = "task001"
+            name = "Buffer overflow"
+            points = 3.0
+            stages = [
+                { id = "first", name = "Crash it", weight = 1, flag = { kind = "user_derived" } },
+                { id = "second", name = "Exploit it", weight = 2, flag = { kind = "user_derived" } },
+            ]
+            [build]
+            directory = "."
+            builder = { shell = { entrypoint = "build.sh" } }
+            output = [{ kind = { resource = "vuln" } }]
+            enabled_modes = ["sequential"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let variables = BTreeMap::from([("task.name".to_string(), "Overflow".to_string())]);
+        assert_eq!(
+            render("# {{task.name}}\n{{ task.name }}!", &variables).unwrap(),
+            "# Overflow\nOverflow!"
+        );
+        assert_eq!(render("No variables", &variables).unwrap(), "No variables");
+        assert!(matches!(
+            render("{{ task.nme }}", &variables),
+            Err(BuildError::TemplateError(_))
+        ));
+        assert!(matches!(
+            render("{{ task.name", &variables),
+            Err(BuildError::TemplateError(_))
+        ));
+    }
+
+    #[test]
+    fn test_variables() {
+        let task = task();
+        let uuid = Uuid::now_v7();
+        let mut instance = IntermediateOutput::new(
+            uuid,
+            Vec::new(),
+            "/out".into(),
+            vec![OutputItem::new(OutputKind::Resource("/out/vuln".into()))],
+        );
+        instance.meta = Some(Challenge {
+            uuid,
+            flag: None,
+            url: Some("https://example.com".to_string()),
+            metadata: [("port".to_string(), serde_json::json!(22))].into(),
+        });
+        let variables = variables(&task, &instance);
+        assert_eq!(variables["uuid"], uuid.to_string());
+        assert_eq!(variables["stage.second.name"], "Exploit it");
+        assert_eq!(variables["stage.second.points"], "2");
+        assert_eq!(variables["links.vuln"], "vuln");
+        assert_eq!(variables["meta.url"], "https://example.com");
+        assert_eq!(variables["meta.port"], "22");
+
+        instance.outputs[0].set_link("https://bucket/vuln".to_string());
+        assert_eq!(
+            super::variables(&task, &instance)["links.vuln"],
+            "https://bucket/vuln"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Verification of the built variants with the solver of the task
+//!
+//! The solver gets a directory with copies of the resource files of a single variant, the same files a student gets.
+//! The flags it prints to stdout, separated by whitespace, are compared with the flags of the variant.
+//! A flag is accepted in the encased form `flag{id:suffix}`, as `id:suffix` or as the plain suffix.
+//!
+//! The solver does not inherit the environment, which has the secrets of the flags. Only `PATH`, `HOME` and `LANG`
+//! are passed. When the task builds in a container or a sandbox, the solver runs in it too, with a copy of the
+//! entrypoint, since the task directory is not visible there.
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::build_process::{IntermediateOutput, TaskBuildContainer, run_builder};
+use crate::config::{Builder, OutputKind, Task};
+use crate::container::container_command;
+use crate::errors::BuildError;
+use crate::flag_generator::Flag;
+use crate::sandbox::sandboxed_command;
+
+/// Variables passed from the environment to a solver which runs on the host
+const SOLVER_ENV: [&str; 3] = ["PATH", "HOME", "LANG"];
+/// Name of the copy of the entrypoint in a container or a sandbox
+const SOLVER_SCRIPT: &str = "solver.sh";
+
+/// Run the solver for every variant of the build. Returns the failed variants with their errors.
+///
+/// Fails immediately only if the task has no solver configured.
+pub fn verify_build<'a>(
+    build: &'a TaskBuildContainer,
+) -> Result<Vec<(&'a IntermediateOutput, BuildError)>, BuildError> {
+    if build.task.build.solver.is_none() {
+        return Err(BuildError::SolverError(format!(
+            "Task {} has no solver configured",
+            build.task.id
+        )));
+    }
+    let mut failures = Vec::new();
+    for instance in &build.outputs {
+        match verify_instance(&build.task, instance) {
+            Ok(()) => tracing::info!(
+                "Variant {} of task {} was solved successfully",
+                instance.uuid,
+                build.task.id
+            ),
+            Err(error) => {
+                tracing::error!(
+                    "Variant {} of task {} failed verification: {}",
+                    instance.uuid,
+                    build.task.id,
+                    error
+                );
+                failures.push((instance, error));
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Run the solver for a single variant and check that it recovers all the stage flags
+pub fn verify_instance(task: &Task, instance: &IntermediateOutput) -> Result<(), BuildError> {
+    let Some(solver) = &task.build.solver else {
+        return Err(BuildError::SolverError(format!(
+            "Task {} has no solver configured",
+            task.id
+        )));
+    };
+    let entrypoint = task
+        .build
+        .directory
+        .join(&solver.entrypoint)
+        .canonicalize()
+        .map_err(|e| {
+            BuildError::SolverError(format!(
+                "Solver '{}' of task {} not found: {}",
+                solver.entrypoint, task.id, e
+            ))
+        })?;
+
+    let tempdir = tempfile::tempdir().map_err(|e| {
+        BuildError::TemporaryDirectoryFail(format!("Solver directory cannot be created: {e}"))
+    })?;
+    let workdir = tempdir.path().join("files");
+    let solver_dir = tempdir.path().join("solver");
+    for dir in [&workdir, &solver_dir] {
+        std::fs::create_dir(dir).map_err(|e| {
+            BuildError::TemporaryDirectoryFail(format!("Solver directory cannot be created: {e}"))
+        })?;
+    }
+    copy_resources(instance, &workdir)?;
+
+    let command = solver_command(task, &entrypoint, &workdir, &solver_dir)?;
+    let deadline = Instant::now() + Duration::from_secs(solver.timeout);
+    let output = run_builder(command, None, Some(deadline), &|line| {
+        tracing::debug!("{}", line)
+    })
+    .map_err(|e| {
+        BuildError::SolverError(format!(
+            "The solver of task {} could not be started: {}",
+            task.id, e
+        ))
+    })?
+    .ok_or_else(|| {
+        BuildError::SolverError(format!(
+            "The solver of task {} did not finish in {} seconds",
+            task.id, solver.timeout
+        ))
+    })?;
+    if !output.status.success() {
+        return Err(BuildError::SolverError(format!(
+            "The solver of task {} failed with {}: {}",
+            task.id,
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let missing = missing_flags(&instance.stage_flags, &stdout);
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(BuildError::SolverError(format!(
+            "The solver of task {} did not recover the flags of {}",
+            task.id,
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Command which runs the solver in the isolation of the builder of the task
+fn solver_command(
+    task: &Task,
+    entrypoint: &Path,
+    workdir: &Path,
+    solver_dir: &Path,
+) -> Result<Command, BuildError> {
+    // Mounted at the same path, the writable directory can be read as well
+    let mounted = [solver_dir.to_path_buf()];
+    match (&task.build.builder, &task.build.sandbox) {
+        (Builder::Container(container), sandbox) => {
+            let script = copy_solver(task, entrypoint, solver_dir)?;
+            container_command(
+                container,
+                sandbox.as_ref(),
+                "sh",
+                &[&script],
+                workdir,
+                &mounted,
+                isolated_envs(),
+            )
+        }
+        (_, Some(sandbox)) => {
+            let script = copy_solver(task, entrypoint, solver_dir)?;
+            sandboxed_command(
+                sandbox,
+                "sh",
+                &[&script],
+                workdir,
+                &mounted,
+                isolated_envs(),
+            )
+        }
+        (_, None) => {
+            let mut command = Command::new("sh");
+            command
+                .arg(entrypoint)
+                .env_clear()
+                .envs(SOLVER_ENV.iter().filter_map(|name| {
+                    std::env::var(name)
+                        .ok()
+                        .map(|value| (name.to_string(), value))
+                }))
+                .current_dir(workdir);
+            Ok(command)
+        }
+    }
+}
+
+/// Copy the entrypoint for a container or a sandbox, returns the path of the copy
+fn copy_solver(task: &Task, entrypoint: &Path, solver_dir: &Path) -> Result<String, BuildError> {
+    let script = solver_dir.join(SOLVER_SCRIPT);
+    std::fs::copy(entrypoint, &script).map_err(|e| {
+        BuildError::SolverError(format!(
+            "The solver of task {} cannot be copied: {}",
+            task.id, e
+        ))
+    })?;
+    Ok(script.to_string_lossy().into_owned())
+}
+
+/// The container and the sandbox set their own `PATH` and `HOME`
+fn isolated_envs() -> HashMap<String, String> {
+    std::env::var("LANG")
+        .ok()
+        .map(|lang| ("LANG".to_string(), lang))
+        .into_iter()
+        .collect()
+}
+
+/// Copy the files given to the students, the readme and the internal files are left out
+fn copy_resources(instance: &IntermediateOutput, destination: &Path) -> Result<(), BuildError> {
+    for item in &instance.outputs {
+        if let OutputKind::Resource(path) = &item.kind {
+            let source = if path.is_absolute() {
+                path.clone()
+            } else {
+                instance.task_instance_dir.join(path)
+            };
+            let Some(file_name) = source.file_name() else {
+                continue;
+            };
+            std::fs::copy(&source, destination.join(file_name)).map_err(|e| {
+                BuildError::SolverError(format!(
+                    "Resource file '{}' cannot be copied for the solver: {}",
+                    source.display(),
+                    e
+                ))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Identifiers of the flags which are not in the solver output
+fn missing_flags(flags: &[Flag], solver_output: &str) -> Vec<String> {
+    let tokens: Vec<&str> = solver_output.split_whitespace().collect();
+    flags
+        .iter()
+        .filter(|flag| {
+            let suffix = flag.flag_string();
+            let suffix = suffix
+                .split_once(':')
+                .map_or(suffix.as_str(), |(_, suffix)| suffix);
+            !tokens.iter().any(|token| {
+                *token == flag.encased() || *token == flag.flag_string() || *token == suffix
+            })
+        })
+        .map(|flag| flag.get_identifier().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_flags() {
+        let flags = vec![
+            Flag::new_random_flag("task001a".to_string(), 16),
+            Flag::new_random_flag("task001b".to_string(), 16),
+        ];
+        let suffix = flags[1]
+            .flag_string()
+            .split_once(':')
+            .unwrap()
+            .1
+            .to_string();
+
+        let output = format!("{}\n{}\n", flags[0].encased(), flags[1].flag_string());
+        assert!(missing_flags(&flags, &output).is_empty());
+        let output = format!("Recovered: {} {suffix}", flags[0].flag_string());
+        assert!(missing_flags(&flags, &output).is_empty());
+
+        assert_eq!(
+            missing_flags(&flags, flags[0].encased()),
+            vec!["task001b".to_string()]
+        );
+        // Part of a flag is not enough
+        assert_eq!(missing_flags(&flags, &suffix[..8]).len(), 2);
+    }
+}
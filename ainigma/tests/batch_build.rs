@@ -5,6 +5,7 @@ use assert_cmd::Command;
 #[test]
 fn batch_simple_validate() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
     Command::cargo_bin("ainigma")?
         .args([
             "--config",
@@ -15,6 +16,7 @@ fn batch_simple_validate() -> Result<(), Box<dyn std::error::Error>> {
             "--output-dir",
             temp_dir.path().to_str().unwrap(),
         ])
+        .env("AINIGMA_CACHE_DIR", cache_dir.path())
         .assert()
         .success();
 
@@ -0,0 +1,57 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml
+
+const UUID: &str = "01908498-ac98-708d-b886-b6f2747ef785";
+
+fn generate(
+    output_dir: &std::path::Path,
+    cache_dir: &std::path::Path,
+    extra: &[&str],
+) -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+    Ok(Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "generate",
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+            "--task",
+            "task001",
+            "--uuid",
+            UUID,
+        ])
+        .args(extra)
+        .env("AINIGMA_CACHE_DIR", cache_dir)
+        .env("RUST_LOG", "info")
+        .assert()
+        .success())
+}
+
+#[test]
+fn cli_repeated_build_uses_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let cache_dir = tempfile::tempdir()?;
+    let first = tempfile::tempdir()?;
+    let second = tempfile::tempdir()?;
+    let third = tempfile::tempdir()?;
+    let reused = predicate::str::contains("Reusing the cached build");
+
+    generate(first.path(), cache_dir.path(), &[])?.stdout(reused.clone().not());
+    generate(second.path(), cache_dir.path(), &[])?.stdout(reused.clone());
+    generate(third.path(), cache_dir.path(), &["--no-cache"])?.stdout(reused.not());
+
+    let script = |dir: &std::path::Path| {
+        std::fs::read_to_string(dir.join(UUID).join("task001").join("secret.sh"))
+    };
+    assert_eq!(script(first.path())?, script(second.path())?);
+    assert_eq!(script(first.path())?, script(third.path())?);
+
+    Command::cargo_bin("ainigma")?
+        .args(["cache", "prune", "--all"])
+        .env("AINIGMA_CACHE_DIR", cache_dir.path())
+        .env("RUST_LOG", "info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 entries"));
+    Ok(())
+}
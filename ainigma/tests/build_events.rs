@@ -0,0 +1,113 @@
+use ainigma::build_process::{BuildOptions, build_batch, build_sequential};
+use ainigma::config::read_check_toml;
+use ainigma::events::{BuildEvent, EventSink};
+use uuid::Uuid;
+// uses data/configs/simple_shell.toml and data/configs/batch_count.toml
+
+fn build_events(config: &std::path::Path) -> Vec<BuildEvent> {
+    let config = read_check_toml(config.as_os_str()).unwrap();
+    let task = config.get_task_by_id("task001").unwrap();
+    let (events, receiver) = EventSink::channel();
+    let options = BuildOptions {
+        events,
+        ..Default::default()
+    };
+    let output_dir = tempfile::tempdir().unwrap();
+    let uuid = Uuid::now_v7();
+    let _ = build_sequential(&config, task, uuid, output_dir.path(), 1, false, &options);
+    drop(options);
+    receiver.iter().collect()
+}
+
+#[test]
+fn events_of_successful_build() {
+    let events = build_events("tests/data/configs/simple_shell.toml".as_ref());
+    let BuildEvent::VariantStarted { task_id, uuid } = &events[0] else {
+        panic!("The first event should start the variant: {events:?}");
+    };
+    assert_eq!(task_id, "task001");
+    let lines: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            BuildEvent::BuilderOutput {
+                line,
+                uuid: Some(line_uuid),
+                ..
+            } if line_uuid == uuid => Some(line.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(lines[0], "Building very simple bash script...");
+    assert_eq!(
+        events[events.len() - 2..],
+        [
+            BuildEvent::OutputsValidated {
+                task_id: task_id.clone(),
+                uuid: *uuid
+            },
+            BuildEvent::VariantFinished {
+                task_id: task_id.clone(),
+                uuid: *uuid,
+                cached: false
+            }
+        ]
+    );
+}
+
+#[test]
+fn events_of_failed_build() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = std::fs::read_to_string("tests/data/configs/simple_shell.toml")
+        .unwrap()
+        .replace("\"build.sh\"", "\"missing.sh\"");
+    let config_path = temp_dir.path().join("missing_entrypoint.toml");
+    std::fs::write(&config_path, config).unwrap();
+
+    let events = build_events(&config_path);
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert!(matches!(events[0], BuildEvent::VariantStarted { .. }));
+    let BuildEvent::Failed { uuid, error, .. } = &events[1] else {
+        panic!("The build should fail: {events:?}");
+    };
+    assert!(uuid.is_some());
+    assert!(error.contains("non-zero exit code"), "{error}");
+}
+
+#[test]
+fn events_of_batch_build() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let builder = temp_dir.path().join("failing_batch.sh");
+    std::fs::write(&builder, "echo Building the batch\nexit 1\n").unwrap();
+    let config = std::fs::read_to_string("tests/data/configs/batch_count.toml")
+        .unwrap()
+        .replace("custom_entry.sh", builder.to_str().unwrap());
+    let config_path = temp_dir.path().join("failing_batch.toml");
+    std::fs::write(&config_path, config).unwrap();
+    let config = read_check_toml(config_path.as_os_str()).unwrap();
+    let task = config.get_task_by_id("task002").unwrap();
+
+    let (events, receiver) = EventSink::channel();
+    let options = BuildOptions {
+        events,
+        ..Default::default()
+    };
+    let output_dir = tempfile::tempdir().unwrap();
+    assert!(build_batch(&config, task, output_dir.path(), false, &options).is_err());
+    drop(options);
+    let events: Vec<BuildEvent> = receiver.iter().collect();
+
+    let started = events
+        .iter()
+        .filter(|event| matches!(event, BuildEvent::VariantStarted { .. }))
+        .count();
+    assert_eq!(started, 3, "{events:?}");
+    assert!(events.contains(&BuildEvent::BuilderOutput {
+        task_id: "task002".to_string(),
+        uuid: None,
+        line: "Building the batch".to_string(),
+    }));
+    assert!(matches!(
+        events.last(),
+        Some(BuildEvent::Failed { uuid: None, .. })
+    ));
+}
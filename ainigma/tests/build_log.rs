@@ -0,0 +1,67 @@
+use ainigma::config::{DEFAULT_BUILD_LOG, DEFAULT_BUILD_MANIFEST};
+use assert_cmd::Command;
+// uses data/configs/simple_shell.toml and data/configs/flaky.toml
+
+mod common;
+
+const FAILING: &str = "0192a1b0-0000-7000-8000-000000000001";
+
+fn generate(config: &str, output_dir: &std::path::Path) -> Command {
+    let mut command = common::generate(config);
+    command.args([
+        "--output-dir",
+        output_dir.to_str().unwrap(),
+        "--uuid",
+        FAILING,
+    ]);
+    command
+}
+
+#[test]
+fn cli_build_log_next_to_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate("tests/data/configs/simple_shell.toml", temp_dir.path())
+        .assert()
+        .success();
+
+    let instance_dir = temp_dir.path().join(FAILING).join("task001");
+    let manifest: serde_json::Value = serde_json::from_reader(std::fs::File::open(
+        instance_dir.join(DEFAULT_BUILD_MANIFEST),
+    )?)?;
+    let log_path = instance_dir.join(DEFAULT_BUILD_LOG);
+    assert_eq!(
+        manifest["outputs"][0]["build_log"],
+        log_path.to_str().unwrap()
+    );
+
+    let log = std::fs::read_to_string(&log_path)?;
+    assert!(log.starts_with("command: sh build.sh\nstatus: exit status: 0\n"));
+    assert!(log.contains(&format!("  TASK_UUID={FAILING}\n")));
+    assert!(log.contains("  FLAG_USER_DERIVED_TASK001=<redacted>\n"));
+    assert!(log.contains("--- stdout ---\nBuilding very simple bash script...\n"));
+    let flag = manifest["outputs"][0]["stage_flags"][0]["user_derived_flag"]["suffix"]
+        .as_str()
+        .unwrap();
+    assert!(!log.contains(flag), "The flag should be redacted");
+    Ok(())
+}
+
+#[test]
+fn cli_build_log_of_failed_build() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate("tests/data/configs/flaky.toml", temp_dir.path())
+        .env("FAIL_UUID", FAILING)
+        .assert()
+        .failure();
+
+    let log = std::fs::read_to_string(
+        temp_dir
+            .path()
+            .join(FAILING)
+            .join("task001")
+            .join(DEFAULT_BUILD_LOG),
+    )?;
+    assert!(log.contains("status: exit status: 1\n"), "{log}");
+    assert!(log.ends_with("--- stderr ---\nFailing on purpose\n"));
+    Ok(())
+}
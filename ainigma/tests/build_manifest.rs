@@ -0,0 +1,62 @@
+use ainigma::build_process::BUILD_MANIFEST_SCHEMA_VERSION;
+use ainigma::config::DEFAULT_BUILD_MANIFEST;
+use assert_cmd::Command;
+// uses data/configs/simple_shell.toml
+
+#[test]
+fn cli_schema_is_printed_without_config() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("ainigma")?
+        .args(["--config", "does/not/exist.toml", "schema"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let schema: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(
+        schema["properties"]["schema_version"]["const"],
+        BUILD_MANIFEST_SCHEMA_VERSION
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_flags_passed_as_environment() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "generate",
+            "--output-dir",
+            temp_dir.path().to_str().unwrap(),
+            "--task",
+            "task001",
+        ])
+        .env("AINIGMA_CACHE_DIR", cache_dir.path())
+        .assert()
+        .success();
+
+    let uuid_dir = std::fs::read_dir(temp_dir.path())?
+        .next()
+        .expect("One variant should be built")?;
+    let task_dir = uuid_dir.path().join("task001");
+    let manifest: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(task_dir.join(DEFAULT_BUILD_MANIFEST))?)?;
+    assert_eq!(manifest["schema_version"], BUILD_MANIFEST_SCHEMA_VERSION);
+
+    // The builder reads the flag from FLAG_USER_DERIVED_TASK001
+    let flag = &manifest["outputs"][0]["stage_flags"][0]["user_derived_flag"];
+    let expected = format!(
+        "FLAG=\"{}:{}\"",
+        flag["identifier"].as_str().unwrap(),
+        flag["suffix"].as_str().unwrap()
+    );
+    let script = std::fs::read_to_string(task_dir.join("secret.sh"))?;
+    assert!(
+        script.contains(&expected),
+        "secret.sh should contain the flag from the environment"
+    );
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use ainigma::checksum::sha256_file;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-000000000005";
+
+#[test]
+fn cli_manifest_has_checksums() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    common::generate(common::SIMPLE_SHELL)
+        .args([
+            "--uuid",
+            UUID,
+            "--output-dir",
+            temp_dir.path().to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let instance_dir = temp_dir.path().join(UUID).join("task001");
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+        instance_dir.join("build-manifest.json"),
+    )?)?;
+    let outputs = manifest["outputs"][0]["outputs"].as_array().unwrap();
+    assert_eq!(outputs.len(), 2);
+    for (item, name) in outputs.iter().zip(["readme.txt", "secret.sh"]) {
+        let path = instance_dir.join(name);
+        assert_eq!(item["sha256"], sha256_file(&path)?);
+        assert_eq!(item["size"], std::fs::metadata(&path)?.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Helpers shared by the integration tests
+// Each test binary uses only some of the helpers
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use std::io::Read;
+use std::path::Path;
+
+pub const SIMPLE_SHELL: &str = "tests/data/configs/simple_shell.toml";
+
+/// Arguments before the options of the test, the configuration is set dynamically
+const GENERATE_ARGS: &[&str] = &["generate", "--task", "task001", "--no-cache"];
+
+/// Command building task001 of the configuration without the cache
+pub fn generate(config: &str) -> Command {
+    let mut command = Command::cargo_bin("ainigma").unwrap();
+    command.args(["--config", config]).args(GENERATE_ARGS);
+    command
+}
+
+/// Copy of simple_shell.toml with `from` replaced by `to`, written into the directory
+pub fn config_with(dir: &Path, from: &str, to: &str) -> String {
+    let config = std::fs::read_to_string(SIMPLE_SHELL).unwrap();
+    assert!(config.contains(from), "simple_shell.toml has {from}");
+    let path = dir.join("config.toml");
+    std::fs::write(&path, config.replace(from, to)).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+pub fn read_entry(archive: &mut zip::ZipArchive<std::fs::File>, name: &str) -> String {
+    let mut contents = String::new();
+    archive
+        .by_name(name)
+        .unwrap_or_else(|_| panic!("the archive has {name}"))
+        .read_to_string(&mut contents)
+        .unwrap();
+    contents
+}
@@ -0,0 +1,123 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-000000000009";
+
+#[test]
+fn cli_export_ctfd_challenges() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let output_dir = temp_dir.path().join("build");
+    std::fs::create_dir(&output_dir)?;
+    common::generate(common::SIMPLE_SHELL)
+        .args(["--uuid", UUID, "--output-dir", output_dir.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let challenges_dir = temp_dir.path().join("ctfd");
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "export",
+            "ctfd",
+            "--from",
+            output_dir.to_str().unwrap(),
+            "--output",
+            challenges_dir.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Task 'task001' has user derived flags",
+        ));
+
+    let challenge_dir = challenges_dir.join("task001");
+    let challenge = std::fs::read_to_string(challenge_dir.join("challenge.yml"))?;
+    let lines: Vec<&str> = challenge.lines().collect();
+    assert!(lines.contains(&"name: \"Challenge 5\""));
+    assert!(lines.contains(&"category: \"Network Security Fundamentals\""));
+    assert!(lines.contains(&"value: 2"));
+    assert!(lines.contains(&"type: standard"));
+    let flags = lines.iter().position(|line| *line == "flags:").unwrap();
+    assert!(lines[flags + 1].starts_with("  - \"flag{task001:"));
+    let files = lines.iter().position(|line| *line == "files:").unwrap();
+    assert_eq!(lines[files + 1], "  - \"dist/secret.sh\"");
+    let script = std::fs::read_to_string(challenge_dir.join("dist").join("secret.sh"))?;
+    assert!(!script.is_empty());
+    Ok(())
+}
+
+#[test]
+fn cli_export_ctfd_challenge_per_stage() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = common::config_with(
+        temp_dir.path(),
+        r#"stages = [{ flag = { kind = "user_derived" } }]"#,
+        r#"stages = [
+    { id = "task001", name = "Read it", flag = { kind = "user_derived" } },
+    { id = "task001b", name = "Run it", description = "Run the script.", weight = 3, flag = { kind = "pure_random" } },
+]"#,
+    );
+    let output_dir = temp_dir.path().join("build");
+    std::fs::create_dir(&output_dir)?;
+    common::generate(&config)
+        .args(["--uuid", UUID, "--output-dir", output_dir.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let challenges_dir = temp_dir.path().join("ctfd");
+    Command::cargo_bin("ainigma")?
+        .args(["--config", &config, "export", "ctfd", "--from"])
+        .arg(&output_dir)
+        .arg("--output")
+        .arg(&challenges_dir)
+        .assert()
+        .success();
+
+    let mut directories: Vec<_> = std::fs::read_dir(&challenges_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<_, _>>()?;
+    directories.sort();
+    assert_eq!(directories, ["task001", "task001b"]);
+    let first = std::fs::read_to_string(challenges_dir.join("task001/challenge.yml"))?;
+    let lines: Vec<&str> = first.lines().collect();
+    assert!(lines.contains(&"name: \"Challenge 5: Read it\""));
+    assert!(lines.contains(&"value: 1"), "{first}");
+    let flags = lines.iter().position(|line| *line == "flags:").unwrap();
+    assert!(lines[flags + 1].starts_with("  - \"flag{task001:"));
+    assert!(lines[flags + 2].starts_with("files:"));
+    let second = std::fs::read_to_string(challenges_dir.join("task001b/challenge.yml"))?;
+    let lines: Vec<&str> = second.lines().collect();
+    assert!(lines.contains(&"name: \"Challenge 5: Run it\""));
+    assert!(lines.contains(&"value: 2"), "{second}");
+    assert!(second.contains("Run the script."));
+    let flags = lines.iter().position(|line| *line == "flags:").unwrap();
+    assert!(lines[flags + 1].starts_with("  - \"flag{task001b:"));
+    assert!(challenges_dir.join("task001b/dist/secret.sh").is_file());
+    Ok(())
+}
+
+#[test]
+fn cli_export_ctfd_without_builds_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "export",
+            "ctfd",
+            "--from",
+            temp_dir.path().to_str().unwrap(),
+            "--output",
+            temp_dir.path().join("ctfd").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "There are no built tasks to export",
+        ));
+    Ok(())
+}
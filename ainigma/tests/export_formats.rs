@@ -0,0 +1,73 @@
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-000000000008";
+
+fn export(dir: &std::path::Path, format: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let output = dir.join("exam");
+    common::generate(common::SIMPLE_SHELL)
+        .args([
+            "--uuid",
+            UUID,
+            "moodle",
+            "--disable-upload",
+            "--category",
+            "formats",
+            "--format",
+            format,
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    Ok(std::fs::read_to_string(output)?)
+}
+
+#[test]
+fn cli_export_gift() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let gift = export(temp_dir.path(), "gift")?;
+    assert!(gift.starts_with("$CATEGORY: $course$/formats\n"));
+    assert!(gift.contains(&format!("// {UUID}\n::Challenge 5::[html]")));
+    // The braces and colons of the flags are escaped
+    assert!(gift.contains("{=flag\\{task001\\:"));
+    Ok(())
+}
+
+#[test]
+fn cli_export_csv() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let csv = export(temp_dir.path(), "csv")?;
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("question,uuid,answer,fraction"));
+    let row = lines.next().unwrap();
+    assert!(row.starts_with(&format!("Challenge 5,{UUID},flag{{task001:")));
+    assert!(row.ends_with(",100"));
+    Ok(())
+}
+
+#[test]
+fn cli_export_gift_cannot_embed_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    common::generate(common::SIMPLE_SHELL)
+        .args([
+            "moodle",
+            "--disable-upload",
+            "--embed-limit",
+            "1024",
+            "--category",
+            "formats",
+            "--format",
+            "gift",
+            "--output",
+            temp_dir.path().join("exam.txt").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Files cannot be embedded into the gift format",
+        ));
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml, which puts the flag into a resource file
+
+mod common;
+
+fn generate_with_policy(
+    policy: &str,
+) -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = common::config_with(
+        temp_dir.path(),
+        r#"leak_check = "off""#,
+        &format!("leak_check = \"{policy}\""),
+    );
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+
+    Ok(common::generate(&config)
+        .args(["--output-dir", output_dir.to_str().unwrap()])
+        .env("RUST_LOG", "info")
+        .assert())
+}
+
+#[test]
+fn cli_leak_check_policies() -> Result<(), Box<dyn std::error::Error>> {
+    let leak = predicate::str::contains("leaks the flag task001 in 'secret.sh' as plain text");
+    generate_with_policy("off")?
+        .success()
+        .stdout(leak.clone().not());
+    generate_with_policy("warn")?.success().stdout(leak.clone());
+    generate_with_policy("fail")?
+        .failure()
+        .stdout(leak)
+        .stdout(predicate::str::contains(
+            "Flag found in the files given to the students",
+        ));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+// uses data/configs/meta.toml
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-000000000004";
+
+fn generate(output_dir: &str) -> Result<Command, Box<dyn std::error::Error>> {
+    let mut command = common::generate("tests/data/configs/meta.toml");
+    command.args(["--uuid", UUID, "--output-dir", output_dir]);
+    Ok(command)
+}
+
+#[test]
+fn cli_meta_is_merged_into_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(temp_dir.path().to_str().unwrap())?
+        .assert()
+        .success();
+    let manifest = std::fs::read_to_string(
+        temp_dir
+            .path()
+            .join(UUID)
+            .join("task001/build-manifest.json"),
+    )?;
+    let manifest: serde_json::Value = serde_json::from_str(&manifest)?;
+    let meta = &manifest["outputs"][0]["meta"];
+    assert_eq!(
+        meta["url"],
+        format!("https://challenges.example.com/{UUID}")
+    );
+    assert_eq!(meta["metadata"]["port"], 8080);
+    Ok(())
+}
+
+#[test]
+fn cli_meta_with_unknown_flag_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(temp_dir.path().to_str().unwrap())?
+        .env("META_FLAG", "task001:not-the-flag")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "which was not given to the builder",
+        ));
+    Ok(())
+}
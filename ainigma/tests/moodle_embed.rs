@@ -0,0 +1,55 @@
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-000000000007";
+
+fn generate_quiz(
+    dir: &std::path::Path,
+    embed_limit: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let quiz = dir.join("quiz.xml");
+    common::generate(common::SIMPLE_SHELL)
+        .args([
+            "--uuid",
+            UUID,
+            "moodle",
+            "--disable-upload",
+            "--embed-limit",
+            embed_limit,
+            "--category",
+            "embedded",
+            "--output",
+            quiz.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    Ok(std::fs::read_to_string(quiz)?)
+}
+
+#[test]
+fn cli_moodle_embeds_small_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let quiz = generate_quiz(temp_dir.path(), "65536")?;
+    assert!(quiz.contains("<file name=\"secret.sh\" path=\"/\" encoding=\"base64\">"));
+    assert!(quiz.contains("<a href=\"@@PLUGINFILE@@/secret.sh\""));
+    Ok(())
+}
+
+#[test]
+fn cli_moodle_skips_files_over_embed_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let quiz = generate_quiz(temp_dir.path(), "1")?;
+    assert!(!quiz.contains("<file "));
+    assert!(!quiz.contains("@@PLUGINFILE@@"));
+    Ok(())
+}
+
+#[test]
+fn cli_embed_limit_requires_disabled_upload() -> Result<(), Box<dyn std::error::Error>> {
+    common::generate(common::SIMPLE_SHELL)
+        .args(["moodle", "--embed-limit", "1024", "--category", "embedded"])
+        .assert()
+        .failure();
+    Ok(())
+}
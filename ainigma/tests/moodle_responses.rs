@@ -0,0 +1,116 @@
+use ainigma::flag_generator::{Algorithm, Flag};
+use assert_cmd::Command;
+use predicates::prelude::*;
+use uuid::Uuid;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+const ALICE: &str = "0192a1b0-0000-7000-8000-00000000000a";
+const BOB: &str = "0192a1b0-0000-7000-8000-00000000000b";
+const SECRET: &str = "6b2c0c4535ea5b7c7f4fc603a738840fce80e0c8e2632f139f1aa9d27f540f15";
+
+fn flag(uuid: &str) -> String {
+    Flag::new_user_flag(
+        "task001".to_string(),
+        &Algorithm::HMAC_SHA3_256,
+        SECRET,
+        "task001",
+        &Uuid::parse_str(uuid).unwrap(),
+    )
+    .encased()
+    .to_string()
+}
+
+#[test]
+fn cli_moodle_responses_report() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let output_dir = temp_dir.path().join("build");
+    std::fs::create_dir(&output_dir)?;
+    common::generate(common::SIMPLE_SHELL)
+        .args([
+            "--uuid",
+            ALICE,
+            "--uuid",
+            BOB,
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // Bob answers with the flag of Alice
+    let responses = temp_dir.path().join("responses.csv");
+    std::fs::write(
+        &responses,
+        format!(
+            "Last name,First name,Email address,State,Grade/2.00,Response 1,Right answer 1\n\
+             Doe,Alice,alice@example.com,Finished,2.00,{alice},{alice}\n\
+             Roe,Bob,bob@example.com,Finished,2.00,{alice},{bob}\n\
+             Overall average,,,,2.00,,\n",
+            alice = flag(ALICE),
+            bob = flag(BOB),
+        ),
+    )?;
+    let report = temp_dir.path().join("report.csv");
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "responses",
+            responses.to_str().unwrap(),
+            "--from",
+            output_dir.to_str().unwrap(),
+            "--output",
+            report.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 of 2 responses are suspicious"));
+    let report = std::fs::read_to_string(report)?;
+    let mut lines = report.lines();
+    assert_eq!(
+        lines.next(),
+        Some("student,slot,task,uuid,correct_flags,total_flags,unlisted_flags,suspicions")
+    );
+    assert_eq!(
+        lines.next(),
+        Some(
+            format!(
+                "Alice Doe <alice@example.com>,1,task001,{ALICE},1,1,,flags of this variant submitted by Bob Roe <bob@example.com>"
+            )
+            .as_str()
+        )
+    );
+    assert_eq!(
+        lines.next(),
+        Some(
+            format!(
+                "Bob Roe <bob@example.com>,1,task001,{BOB},0,1,,flags of variant {ALICE} of task task001 given to Alice Doe <alice@example.com>"
+            )
+            .as_str()
+        )
+    );
+    assert_eq!(lines.next(), None);
+    Ok(())
+}
+
+#[test]
+fn cli_moodle_responses_without_builds() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let responses = temp_dir.path().join("responses.csv");
+    std::fs::write(&responses, "Last name,First name,Response 1\n")?;
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "responses",
+            responses.to_str().unwrap(),
+            "--from",
+            temp_dir.path().to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("There are no built variants"));
+    Ok(())
+}
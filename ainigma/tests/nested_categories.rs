@@ -0,0 +1,53 @@
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+fn generate(args: &[&str]) -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+    Ok(common::generate(common::SIMPLE_SHELL)
+        .args(["moodle", "--disable-upload"])
+        .args(args)
+        .assert())
+}
+
+#[test]
+fn cli_moodle_nested_categories() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let quiz = temp_dir.path().join("quiz.xml");
+    generate(&["--nested-categories", "--output", quiz.to_str().unwrap()])?.success();
+    let quiz = std::fs::read_to_string(quiz)?;
+    assert!(quiz.contains(
+        "<text>$course$/Cybersecurity/Network Security Fundamentals/Challenge 5/</text>"
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_gift_nested_categories() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let quiz = temp_dir.path().join("quiz.txt");
+    generate(&[
+        "--nested-categories",
+        "--format",
+        "gift",
+        "--output",
+        quiz.to_str().unwrap(),
+    ])?
+    .success();
+    let quiz = std::fs::read_to_string(quiz)?;
+    assert!(quiz.starts_with(
+        "$CATEGORY: $course$/Cybersecurity/Network Security Fundamentals/Challenge 5\n"
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_moodle_needs_a_category() -> Result<(), Box<dyn std::error::Error>> {
+    generate(&[])?
+        .failure()
+        .stderr(predicate::str::contains("--category <CATEGORY>"));
+    generate(&["--nested-categories", "--category", "exam"])?
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
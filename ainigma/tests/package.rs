@@ -0,0 +1,38 @@
+use ainigma::checksum::sha256_file;
+// uses data/configs/simple_shell.toml with a package step
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-00000000abcd";
+
+#[test]
+fn cli_packages_resources() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = common::config_with(
+        temp_dir.path(),
+        "leak_check = \"off\"",
+        "leak_check = \"off\"\npackage = { format = \"tar.gz\" }",
+    );
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+
+    common::generate(&config)
+        .args(["--uuid", UUID, "--output-dir", output_dir.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let instance_dir = output_dir.join(UUID).join("task001");
+    let archive = instance_dir.join("task001-0000abcd.tar.gz");
+    // The original files are kept for the server
+    assert!(instance_dir.join("secret.sh").exists());
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+        instance_dir.join("build-manifest.json"),
+    )?)?;
+    let package = &manifest["outputs"][0]["package"];
+    assert_eq!(
+        package["item"]["kind"]["resource"],
+        archive.canonicalize()?.to_str().unwrap()
+    );
+    assert_eq!(package["item"]["sha256"], sha256_file(&archive)?);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::path::Path;
+use xml::reader::{EventReader, XmlEvent};
+// uses data/configs/simple_shell.toml
+// The documents are validated against the schemas in data/schemas, see the README there
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-00000000000d";
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse(xml: &str) -> Vec<Element> {
+    let mut elements = Vec::new();
+    for event in EventReader::new(xml.as_bytes()) {
+        if let XmlEvent::StartElement {
+            name, attributes, ..
+        } = event.expect("the document is well-formed")
+        {
+            elements.push(Element {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|attribute| (attribute.name.local_name, attribute.value))
+                    .collect(),
+            });
+        }
+    }
+    elements
+}
+
+/// Validate the document with `xmllint` against a schema in data/schemas
+fn validate(schema: &str, name: &str, document: &str) {
+    let schemas = Path::new("tests/data/schemas");
+    let schema = schemas.join(schema);
+    assert!(
+        schema.is_file(),
+        "{} is missing, see {}",
+        schema.display(),
+        schemas.join("README.md").display()
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(name);
+    std::fs::write(&path, document).unwrap();
+    let output = std::process::Command::new("xmllint")
+        .args(["--noout", "--nonet", "--schema"])
+        .arg(&schema)
+        .arg(&path)
+        .env(
+            "XML_CATALOG_FILES",
+            schemas.join("catalog.xml").canonicalize().unwrap(),
+        )
+        .output()
+        .expect("xmllint of libxml2 is installed");
+    assert!(
+        output.status.success(),
+        "{name} is not valid: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+#[ignore = "needs the IMS QTI schemas in tests/data/schemas, see the README there"]
+fn cli_export_qti_package() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let package = temp_dir.path().join("quiz.zip");
+    common::generate(common::SIMPLE_SHELL)
+        .args([
+            "--uuid",
+            UUID,
+            "moodle",
+            "--disable-upload",
+            "--category",
+            "qti",
+            "--format",
+            "qti",
+            "--output",
+            package.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&package)?)?;
+    let manifest = common::read_entry(&mut archive, "imsmanifest.xml");
+    validate("qtiv2p1_imscpv1p2_v1p0.xsd", "imsmanifest.xml", &manifest);
+    let elements = parse(&manifest);
+    assert_eq!(elements[0].name, "manifest");
+    let resources: Vec<&Element> = elements
+        .iter()
+        .filter(|element| element.name == "resource")
+        .collect();
+    assert_eq!(resources.len(), 1);
+    assert_eq!(resources[0].attribute("type"), Some("imsqti_item_xmlv2p1"));
+    let files: Vec<&str> = elements
+        .iter()
+        .filter(|element| element.name == "file")
+        .map(|element| element.attribute("href").unwrap())
+        .collect();
+    let item = format!("item-{UUID}");
+    assert_eq!(files, [format!("{item}.xml"), format!("{item}/secret.sh")]);
+    // Every listed file is in the package
+    for file in files {
+        archive.by_name(file)?;
+    }
+
+    let item_xml = common::read_entry(&mut archive, &format!("{item}.xml"));
+    validate("imsqti_v2p1.xsd", &format!("{item}.xml"), &item_xml);
+    assert!(item_xml.contains(&format!("<a href=\"{item}/secret.sh\">secret.sh</a>")));
+    assert!(item_xml.contains("mapKey=\"flag{task001:"));
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use predicates::prelude::*;
+use std::path::Path;
+// uses data/configs/simple_shell.toml with question metadata
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-00000000000c";
+
+fn config_with_metadata(dir: &Path, metadata: &str) -> String {
+    common::config_with(
+        dir,
+        "[categories.tasks.build]",
+        &format!("[categories.tasks.moodle]\n{metadata}\n\n[categories.tasks.build]"),
+    )
+}
+
+fn generate(
+    config: &str,
+    quiz: &Path,
+) -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+    Ok(common::generate(config)
+        .args([
+            "--uuid",
+            UUID,
+            "moodle",
+            "--disable-upload",
+            "--category",
+            "metadata",
+            "--output",
+            quiz.to_str().unwrap(),
+        ])
+        .assert())
+}
+
+#[test]
+fn cli_moodle_question_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_metadata(
+        temp_dir.path(),
+        r#"name = "{{ task.id }}: {{ task.name }}"
+general_feedback = "<p>The flag was in the script of {{ uuid }}</p>"
+correct_feedback = "Well done"
+incorrect_feedback = "Not a flag of this variant"
+penalty = 0.25
+hints = ["<p>Read the script</p>", "<p>Run it</p>"]
+tags = ["shell", "week 1"]"#,
+    );
+    let quiz = temp_dir.path().join("quiz.xml");
+    generate(&config, &quiz)?.success();
+    let quiz = std::fs::read_to_string(quiz)?;
+    assert!(quiz.contains("<text>task001: Challenge 5</text>"));
+    assert!(quiz.contains(&format!(
+        "<text><![CDATA[<p>The flag was in the script of {UUID}</p>]]></text>"
+    )));
+    assert!(quiz.contains("<penalty>0.25</penalty>"));
+    assert_eq!(quiz.matches("<text>Well done</text>").count(), 2);
+    assert!(!quiz.contains("Correct!"));
+    // Anything else gets the incorrect feedback
+    assert!(quiz.contains("<answer fraction=\"0\" format=\"html\">\n      <text>*</text>\n      <feedback format=\"html\">\n        <text>Not a flag of this variant</text>"));
+    assert_eq!(quiz.matches("<hint format=\"html\">").count(), 2);
+    assert!(quiz.contains("<tag>\n        <text>week 1</text>"));
+    Ok(())
+}
+
+#[test]
+fn cli_moodle_question_metadata_unknown_variable() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_metadata(temp_dir.path(), r#"name = "{{ task.title }}""#);
+    generate(&config, &temp_dir.path().join("quiz.xml"))?
+        .failure()
+        .stdout(predicate::str::contains("unknown variable 'task.title'"));
+    Ok(())
+}
+
+#[test]
+fn cli_question_penalty_out_of_range() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_metadata(temp_dir.path(), "penalty = 2.0");
+    generate(&config, &temp_dir.path().join("quiz.xml"))?
+        .failure()
+        .stdout(predicate::str::contains(
+            "Question penalty must be between 0 and 1",
+        ));
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use predicates::prelude::*;
+use std::path::Path;
+// uses data/configs/simple_shell.toml with a readme template
+
+mod common;
+
+const UUID: &str = "0192a1b0-0000-7000-8000-000000000006";
+
+fn config_with_template(dir: &Path, template: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let template_path = dir.join("readme.tmpl");
+    std::fs::write(&template_path, template)?;
+    Ok(common::config_with(
+        dir,
+        "leak_check = \"off\"",
+        &format!(
+            "leak_check = \"off\"\nreadme_template = \"{}\"",
+            template_path.display()
+        ),
+    ))
+}
+
+#[test]
+fn cli_moodle_question_from_template() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_template(
+        temp_dir.path(),
+        "# {{ task.name }}\n\nVariant {{ uuid }}, worth {{ stage.task001.points }} points.\nDownload {{ links.secret.sh }}.",
+    )?;
+    let quiz = temp_dir.path().join("quiz.xml");
+    common::generate(&config)
+        .args([
+            "--uuid",
+            UUID,
+            "moodle",
+            "--disable-upload",
+            "--category",
+            "templates",
+            "--output",
+            quiz.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    let quiz = std::fs::read_to_string(quiz)?;
+    // The rendered template is Markdown
+    assert!(quiz.contains("<h1>Challenge 5</h1>"));
+    assert!(quiz.contains(&format!("<p>Variant {UUID}, worth 2 points.")));
+    assert!(quiz.contains("Download secret.sh."));
+    Ok(())
+}
+
+#[test]
+fn cli_unknown_template_variable_fails_build() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_template(temp_dir.path(), "{{ task.nmae }}")?;
+    common::generate(&config)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("unknown variable 'task.nmae'"));
+    Ok(())
+}
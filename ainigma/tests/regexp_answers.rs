@@ -0,0 +1,50 @@
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+fn generate(
+    dir: &std::path::Path,
+    answer_matching: &str,
+    format: &str,
+) -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+    Ok(common::generate(common::SIMPLE_SHELL)
+        .args([
+            "moodle",
+            "--disable-upload",
+            "--category",
+            "regexp",
+            "--answer-matching",
+            answer_matching,
+            "--format",
+            format,
+            "--output",
+            dir.join("exam").to_str().unwrap(),
+        ])
+        .assert())
+}
+
+#[test]
+fn cli_moodle_regexp_answers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(temp_dir.path(), "regexp", "moodle-xml")?.success();
+    let quiz = std::fs::read_to_string(temp_dir.path().join("exam"))?;
+    assert!(quiz.contains("<question type=\"regexp\">"));
+    assert!(quiz.contains("<usehint>0</usehint>"));
+    // The correct answer followed by a regular expression for the single flag
+    assert_eq!(quiz.matches("<answer ").count(), 2);
+    assert!(quiz.contains("<text>flag{task001:"));
+    assert!(quiz.contains("<text>^(?:.*?(task001:"));
+    Ok(())
+}
+
+#[test]
+fn cli_moodle_regexp_needs_moodle_xml() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(temp_dir.path(), "regexp-stages", "gift")?
+        .failure()
+        .stdout(predicate::str::contains(
+            "The regexp-stages answer matching is only supported in the moodle-xml format",
+        ));
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml and data/configs/nondeterministic.toml
+
+fn generate(config: &str, extra: &[&str]) -> Result<Command, Box<dyn std::error::Error>> {
+    let mut command = Command::cargo_bin("ainigma")?;
+    command
+        .args(["--config", config, "generate", "--task", "task001"])
+        .args(extra)
+        .args(["--no-cache", "--check-reproducible"])
+        .env("RUST_LOG", "info");
+    Ok(command)
+}
+
+#[test]
+fn cli_reproducible_build() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(
+        "tests/data/configs/simple_shell.toml",
+        &["--output-dir", temp_dir.path().to_str().unwrap(), "-n", "2"],
+    )?
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "All 2 variants of task 'task001' were reproducible",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_nondeterministic_build() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(
+        "tests/data/configs/nondeterministic.toml",
+        &["--output-dir", temp_dir.path().to_str().unwrap()],
+    )?
+    .assert()
+    .failure()
+    .stdout(predicate::str::contains(
+        "'build-info.txt' has different contents",
+    ))
+    .stdout(predicate::str::contains("readme.txt").not());
+    Ok(())
+}
+
+#[test]
+fn cli_pure_random_is_not_reproducible() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = std::fs::read_to_string("tests/data/configs/simple_shell.toml")?
+        .replace("\"user_derived\"", "\"pure_random\"");
+    let config_path = temp_dir.path().join("pure_random.toml");
+    std::fs::write(&config_path, config)?;
+
+    generate(config_path.to_str().unwrap(), &[])?
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("has pure_random flags"));
+    Ok(())
+}
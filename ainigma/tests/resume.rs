@@ -0,0 +1,113 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+// uses data/configs/flaky.toml and data/configs/simple_shell.toml
+
+mod common;
+
+const FAILING: &str = "0192a1b0-0000-7000-8000-000000000001";
+const FAST: &str = "0192a1b0-0000-7000-8000-000000000003";
+
+fn manifest_status(dir: &std::path::Path, uuid: &str) -> serde_json::Value {
+    let manifest = std::fs::read_to_string(dir.join(uuid).join("task001/build-manifest.json"))
+        .expect("The variant has a manifest");
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    manifest["outputs"][0]["status"].clone()
+}
+
+#[test]
+fn cli_resume_partial_build() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let output_dir = temp_dir.path().to_str().unwrap();
+    let generate =
+        |extra: &[&str], fail_uuid: &str| -> Result<Command, Box<dyn std::error::Error>> {
+            let mut command = Command::cargo_bin("ainigma")?;
+            command
+                .args([
+                    "--config",
+                    "tests/data/configs/flaky.toml",
+                    "generate",
+                    "--task",
+                    "task001",
+                    "--no-cache",
+                    "--output-dir",
+                    output_dir,
+                ])
+                .args(extra)
+                .env("FAIL_UUID", fail_uuid)
+                .env("RUST_LOG", "info");
+            Ok(command)
+        };
+    generate(
+        &["--uuid", FAILING, "--uuid", FAST, "--keep-going"],
+        FAILING,
+    )?
+    .assert()
+    .failure();
+    assert_eq!(manifest_status(temp_dir.path(), FAILING), "pending");
+    assert_eq!(manifest_status(temp_dir.path(), FAST), "built");
+    let fast_readme = temp_dir.path().join(FAST).join("task001/readme.txt");
+    let modified = std::fs::metadata(&fast_readme)?.modified()?;
+
+    // The failed variant is built again and one new variant is added
+    generate(&["--resume", "--number", "3"], "")?
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Resuming task 'task001': 1 variants are built and 2 will be built",
+        ))
+        .stdout(predicate::str::contains(
+            "All 2 variants of task 'task001' were built successfully",
+        ));
+    assert_eq!(manifest_status(temp_dir.path(), FAILING), "built");
+    assert_eq!(std::fs::metadata(&fast_readme)?.modified()?, modified);
+    let variants = std::fs::read_dir(temp_dir.path())?.count();
+    assert_eq!(variants, 3);
+    Ok(())
+}
+
+#[test]
+fn cli_resume_requires_output_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ainigma")?;
+    cmd.args([
+        "--config",
+        "tests/data/configs/flaky.toml",
+        "generate",
+        "--task",
+        "task001",
+        "--resume",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("--output-dir"));
+    Ok(())
+}
+
+#[test]
+fn cli_verify_from_output_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let output_dir = temp_dir.path().to_str().unwrap();
+    common::generate(common::SIMPLE_SHELL)
+        .args(["--number", "2", "--output-dir", output_dir])
+        .assert()
+        .success();
+
+    // Verifying an earlier build does not run the builder
+    let mut cmd = Command::cargo_bin("ainigma")?;
+    cmd.args([
+        "--config",
+        "tests/data/configs/simple_shell.toml",
+        "verify",
+        "--task",
+        "task001",
+        "--from",
+        output_dir,
+    ])
+    .env("RUST_LOG", "info")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "All 2 variants of task 'task001' were solved successfully",
+    ))
+    .stdout(predicate::str::contains("Starting building").not());
+    Ok(())
+}
@@ -18,12 +18,14 @@ const SIMPLE_SHELL_ARGS: &[&str] = &[
 #[test]
 fn cli_simple_sequentical_validate() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
     let mut args = SIMPLE_SHELL_ARGS.to_vec();
     args.insert(4, temp_dir.path().to_str().unwrap());
 
     Command::cargo_bin("ainigma")?
         .args(args)
         .env("RUST_LOG", "info")
+        .env("AINIGMA_CACHE_DIR", cache_dir.path())
         .assert()
         .success();
 
@@ -60,6 +62,7 @@ fn cli_simple_sequentical_validate() -> Result<(), Box<dyn std::error::Error>> {
 #[test]
 fn cli_simple_shell_with_moodle() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
     let mut args = SIMPLE_SHELL_ARGS.to_vec();
     args.insert(4, temp_dir.path().to_str().unwrap());
     args.push("moodle");
@@ -73,6 +76,7 @@ fn cli_simple_shell_with_moodle() -> Result<(), Box<dyn std::error::Error>> {
     Command::cargo_bin("ainigma")?
         .args(args)
         .env("RUST_LOG", "info")
+        .env("AINIGMA_CACHE_DIR", cache_dir.path())
         .assert()
         .success();
     // check if exam file exists in the output dir
@@ -0,0 +1,9 @@
+#!/bin/sh
+# Fails for the variant given in FAIL_UUID, the other variants take BUILD_SECONDS to build
+set -e
+if [ "$TASK_UUID" = "$FAIL_UUID" ]; then
+    echo "Failing on purpose" >&2
+    exit 1
+fi
+sleep "${BUILD_SECONDS:-0}"
+echo "Find the flag" > "$OUTPUT_DIR/readme.txt"
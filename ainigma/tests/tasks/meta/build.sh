@@ -0,0 +1,18 @@
+#!/bin/sh
+# Reports the flag and the address of the deployed variant in the meta file
+# META_FLAG can be set to report a different flag
+set -e
+echo "Connect to your challenge instance" > "$OUTPUT_DIR/readme.txt"
+cat << EOF2 > "$OUTPUT_DIR/meta.json"
+{
+  "task": "$TASK_ID",
+  "challenges": [
+    {
+      "uuid": "$TASK_UUID",
+      "flag": "${META_FLAG:-$FLAG_USER_DERIVED_TASK001}",
+      "url": "https://challenges.example.com/$TASK_UUID",
+      "metadata": { "port": 8080, "username": "student" }
+    }
+  ]
+}
+EOF2
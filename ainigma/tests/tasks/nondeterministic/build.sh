@@ -0,0 +1,7 @@
+#!/bin/sh
+# Embeds the build time into a resource file, so two builds of the same variant differ
+set -e
+echo "Built at $(date +%s%N)" > "$OUTPUT_DIR/build-info.txt"
+cat << EOF2 > "$OUTPUT_DIR/readme.txt"
+The flag is not in the build information.
+EOF2
@@ -5,8 +5,6 @@
 # Exit on fail
 set -e
 echo "Building very simple bash script..."
-# Use jq to extract each flag from JSON (assumes JSON field 'flags' is an array)
-# Safe index when no batching
 # Builder always provides the BUILD_MANIFEST
 
 # Check if BUILD_MANIFEST is set
@@ -15,9 +13,13 @@ if [ -z "$BUILD_MANIFEST" ]; then
     exit 1
 fi
 
-# Extract values from BUILD_MANIFEST
-FLAG=$(jq -r '.outputs[0].stage_flags[0].user_derived.suffix' "$BUILD_MANIFEST")
-OUTPUT_DIR=$(jq -r '.outputs[0].task_instance_dir' "$BUILD_MANIFEST")
+# Flags of each stage and the output directory are passed as environment variables
+# The same values are available from BUILD_MANIFEST, e.g. jq -r '.outputs[0].task_instance_dir'
+FLAG="$FLAG_USER_DERIVED_TASK001"
+if [ -z "$FLAG" ] || [ -z "$OUTPUT_DIR" ]; then
+    echo "Error: FLAG_USER_DERIVED_TASK001 or OUTPUT_DIR environment variable is not set"
+    exit 1
+fi
 
 # echo "OUTPUT_DIR=$OUTPUT_DIR" > "debug_$(date +%s)_$RANDOM.txt"
 
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Solves the task from the distributed files only, the working directory contains secret.sh
+set -e
+sed -n 's/^FLAG="\(.*\)"$/\1/p' secret.sh
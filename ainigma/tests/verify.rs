@@ -0,0 +1,105 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+// uses data/configs/simple_shell.toml
+
+mod common;
+
+#[test]
+fn cli_verify_solvable_variants() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            "tests/data/configs/simple_shell.toml",
+            "verify",
+            "--task",
+            "task001",
+            "-n",
+            "2",
+            "--no-cache",
+        ])
+        .env("RUST_LOG", "info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "All 2 variants of task 'task001' were solved successfully",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_generate_with_broken_solver() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    // The build script is not able to solve the task
+    let config = common::config_with(temp_dir.path(), "solver.sh", "build.sh");
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+
+    common::generate(&config)
+        .args(["--output-dir", output_dir.to_str().unwrap(), "--verify"])
+        .env("RUST_LOG", "info")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("failed verification"));
+    Ok(())
+}
+
+fn config_with_solver(
+    dir: &std::path::Path,
+    solver: &str,
+    timeout: u64,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let solver_path = dir.join("solver.sh");
+    std::fs::write(&solver_path, solver)?;
+    Ok(common::config_with(
+        dir,
+        "solver = { entrypoint = \"solver.sh\" }",
+        &format!(
+            "solver = {{ entrypoint = \"{}\", timeout = {timeout} }}",
+            solver_path.display()
+        ),
+    ))
+}
+
+#[test]
+fn cli_verify_solver_without_secrets() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_solver(
+        temp_dir.path(),
+        "[ -z \"$AINIGMA_USER_DERIVED_SECRET\" ] || exit 1\nsed -n 's/^FLAG=\"\\(.*\\)\"$/\\1/p' secret.sh\n",
+        60,
+    )?;
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            &config,
+            "verify",
+            "--task",
+            "task001",
+            "--no-cache",
+        ])
+        .env("AINIGMA_USER_DERIVED_SECRET", "visible to the builder only")
+        .env("RUST_LOG", "info")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("were solved successfully"));
+    Ok(())
+}
+
+#[test]
+fn cli_verify_solver_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let config = config_with_solver(temp_dir.path(), "sleep 30\n", 1)?;
+    Command::cargo_bin("ainigma")?
+        .args([
+            "--config",
+            &config,
+            "verify",
+            "--task",
+            "task001",
+            "--no-cache",
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("did not finish in 1 seconds"));
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::time::{Duration, Instant};
+// uses data/configs/flaky.toml
+
+mod common;
+
+const FAILING: &str = "0192a1b0-0000-7000-8000-000000000001";
+const SLOW: &str = "0192a1b0-0000-7000-8000-000000000002";
+const FAST: &str = "0192a1b0-0000-7000-8000-000000000003";
+
+fn generate(uuids: &[&str], extra: &[&str]) -> Result<Command, Box<dyn std::error::Error>> {
+    let mut command = common::generate("tests/data/configs/flaky.toml");
+    command
+        .args(uuids.iter().flat_map(|uuid| ["--uuid", uuid]))
+        .args(extra)
+        .env("FAIL_UUID", FAILING)
+        .env("RUST_LOG", "info");
+    Ok(command)
+}
+
+#[test]
+fn cli_failure_cancels_running_builds() -> Result<(), Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    generate(&[SLOW, FAILING], &["--jobs", "2"])?
+        .env("BUILD_SECONDS", "60")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!(
+            "Variant 2 with UUID {FAILING} failed"
+        )))
+        .stdout(predicate::str::contains(
+            "Built 0 of 2 variants of task 'task001': 1 failed and 1 were cancelled",
+        ));
+    assert!(started.elapsed() < Duration::from_secs(30));
+    Ok(())
+}
+
+#[test]
+fn cli_keep_going() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    generate(
+        &[FAILING, FAST, SLOW],
+        &[
+            "--jobs",
+            "1",
+            "--keep-going",
+            "--output-dir",
+            temp_dir.path().to_str().unwrap(),
+        ],
+    )?
+    .assert()
+    .failure()
+    .stdout(predicate::str::contains(
+        "Built 2 of 3 variants of task 'task001': 1 failed and 0 were cancelled",
+    ));
+    // The variants after the failed one were still built
+    for uuid in [FAST, SLOW] {
+        assert!(
+            temp_dir
+                .path()
+                .join(uuid)
+                .join("task001/readme.txt")
+                .exists()
+        );
+    }
+    Ok(())
+}